@@ -1,29 +1,409 @@
-use chrono::{DateTime, Local, TimeZone};
+use crate::title::{trailing_group, BookTitle, TitleStyle};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
 use nom::{
-    bytes::complete::{tag, take, take_until},
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take, take_until},
     character::complete::{digit1, line_ending, not_line_ending},
-    combinator::map,
-    multi::{many_till, separated_list0},
-    sequence::{delimited, terminated, tuple},
+    combinator::{map, map_parser, map_res, opt, rest, value, verify},
+    sequence::{delimited, preceded, terminated, tuple},
     IResult,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::num::ParseIntError;
+use std::path::PathBuf;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BookClips {
     pub book_name: String,
-    pub author: String,
+    pub authors: Vec<Author>,
     pub clips: Vec<Clip>,
+    // Details of the book from a library, like Calibre, when it was found there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<BookMetadata>,
 }
 
+/// Details of a book the clips do not have, read from a library like Calibre
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct BookMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+    // Position in the series, which can be decimal like 1.5
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_index: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isbn: Option<String>,
+    // Language code, like `eng`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<NaiveDate>,
+    // Cover image of the library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<PathBuf>,
+}
+
+/// Author of a book, written the same way whatever the source, see `Author::parse`
+#[derive(Debug, Serialize, Deserialize, Clone, Eq)]
+pub struct Author {
+    // Name as displayed, like `J. R. R. Tolkien`
+    pub name: String,
+    // Name to sort authors by, like `Tolkien, J. R. R.`
+    pub sort: String,
+}
+
+/// Placeholder Kindle saves instead of the text of the clips past the clipping limit of a book,
+/// which publishers set to limit how much of it can be exported
+const CLIPPING_LIMIT_PLACEHOLDER: &str = "clipping limit";
+
+/// Author displayed for the books without author, like personal documents
+pub const UNKNOWN_AUTHOR: &str = "Unknown author";
+
+/// Suffixes kept at the end of the names, like `Martin Luther King Jr.`
+const NAME_SUFFIXES: [&str; 7] = ["Jr.", "Jr", "Sr.", "Sr", "II", "III", "IV"];
+
+/// Particles that belong to the last name when they precede it, like `Le` in `Ursula K. Le Guin`
+const SURNAME_PARTICLES: [&str; 15] = [
+    "da", "de", "del", "della", "der", "des", "di", "du", "la", "le", "st.", "ten", "ter", "van",
+    "von",
+];
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Clip {
     pub book: String,
-    pub author: String,
+    pub authors: Vec<Author>,
+    // Authors as written by the source, which identify the clip, see `Clip::id`
+    #[serde(default)]
+    pub source_author: String,
+    pub kind: ClipKind,
     pub content: String,
     pub date: DateTime<Local>,
-    // Start/End locations
+    // Start/End locations, equal for notes and bookmarks
     pub location: (usize, usize),
+    // Not present for books without page numbers
+    pub page: Option<usize>,
+    // Chapter title, when the source knows it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chapter: Option<String>,
+    // Position in the book between 0 and 1, when the source knows it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    // Highlight color, like `yellow`, when the source knows it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    // Past the clipping limit of the book, so Kindle saved a placeholder instead of the text
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    // Estimated from a position the location map of the book is needed for, so the clip is never
+    // matched on its location, see `is_same_clip`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub approximate_location: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipKind {
+    #[default]
+    Highlight,
+    Note,
+    Bookmark,
+}
+
+impl BookClips {
+    /// Returns the names of the authors, see `author_names`, or `UNKNOWN_AUTHOR` if the book has
+    /// none
+    pub fn author(&self) -> String {
+        display_author(&self.authors)
+    }
+
+    /// Returns the name of the book split in its parts, see `BookTitle::parse`
+    pub fn title(&self) -> BookTitle {
+        BookTitle::parse(&self.book_name)
+    }
+
+    /// Returns the name of the book to display, in the given style
+    pub fn display_name(&self, style: TitleStyle) -> String {
+        self.title().format(style)
+    }
+
+    /// Returns the clips past the clipping limit of the book, whose text has to be recovered
+    /// manually from their location
+    pub fn truncated_clips(&self) -> Vec<&Clip> {
+        self.clips.iter().filter(|clip| clip.truncated).collect()
+    }
+
+    /// Returns the highlights of the book, each with the note the user attached to it
+    ///
+    /// Kindle saves notes as separate clips located at the end of the highlight they belong to.
+    /// Notes that do not match any highlight are returned on their own, and bookmarks and
+    /// truncated clips are skipped.
+    /// # Returns
+    /// * `Vec<(&Clip, Option<&Clip>)>` - The highlights or lone notes, and their attached note
+    pub fn annotated_highlights(&self) -> Vec<(&Clip, Option<&Clip>)> {
+        let highlights: Vec<&Clip> = self
+            .clips
+            .iter()
+            .filter(|clip| clip.kind == ClipKind::Highlight && !clip.truncated)
+            .collect();
+
+        let mut annotated: Vec<(&Clip, Option<&Clip>)> = highlights
+            .iter()
+            .map(|highlight| (*highlight, None))
+            .collect();
+
+        for note in self
+            .clips
+            .iter()
+            .filter(|clip| clip.kind == ClipKind::Note && !clip.truncated)
+        {
+            // Preferring the highlight ending on the note, then any highlight containing it
+            let position = highlights
+                .iter()
+                .position(|highlight| highlight.location.1 == note.location.0)
+                .or_else(|| {
+                    highlights.iter().position(|highlight| {
+                        highlight.location.0 <= note.location.0
+                            && note.location.0 <= highlight.location.1
+                    })
+                });
+
+            match position {
+                Some(position) if annotated[position].1.is_none() => {
+                    annotated[position].1 = Some(note);
+                }
+                _ => annotated.push((note, None)),
+            }
+        }
+
+        // Keeping the reading order
+        annotated.sort_by_key(|(clip, _)| clip.location.0);
+
+        annotated
+    }
+
+    /// Removes the clips saved again and the highlights extended afterwards, see `group_clips`
+    fn dedupe_clips(&mut self) {
+        let clips = std::mem::take(&mut self.clips);
+        let ids: Vec<String> = clips.iter().map(Clip::id).collect();
+
+        // Position of the last clip saved with each identifier
+        let last_saved: HashMap<&str, usize> = ids
+            .iter()
+            .enumerate()
+            .map(|(idx, id)| (id.as_str(), idx))
+            .collect();
+
+        // Highlights by start location, to only look at the ones starting before a highlight
+        let mut highlights: Vec<&Clip> = clips
+            .iter()
+            .filter(|clip| clip.kind == ClipKind::Highlight)
+            .collect();
+        highlights.sort_by_key(|clip| clip.location.0);
+
+        let kept: Vec<bool> = clips
+            .iter()
+            .enumerate()
+            .map(|(idx, clip)| {
+                let saved_again = last_saved[ids[idx].as_str()] != idx;
+
+                // Highlights without text cannot be found in a longer one
+                let content = clip.content.trim();
+                let starting_before =
+                    highlights.partition_point(|other| other.location.0 <= clip.location.0);
+                let extended = clip.kind == ClipKind::Highlight
+                    && !content.is_empty()
+                    && highlights[..starting_before].iter().any(|other| {
+                        other.location != clip.location
+                            && clip.location.1 <= other.location.1
+                            && other.content.contains(content)
+                    });
+
+                !saved_again && !extended
+            })
+            .collect();
+
+        self.clips = clips
+            .into_iter()
+            .zip(kept)
+            .filter_map(|(clip, kept)| kept.then_some(clip))
+            .collect();
+    }
+}
+
+impl Author {
+    /// Parses the name of an author, written `First Last` or `Last, First`
+    ///
+    /// Initials are spaced and names are displayed first name first, so the sources agree.
+    /// * Example: `Tolkien, J.R.R.` gives the name `J. R. R. Tolkien` and the sort form
+    ///   `Tolkien, J. R. R.`
+    /// # Returns
+    /// * `Option<Author>` - The author, or nothing if the name is blank
+    pub fn parse(name: &str) -> Option<Self> {
+        // Spacing initials, like `J.R.R.`, and removing roles in parentheses, like `(Ed.)`
+        let mut spaced = String::new();
+        let mut depth = 0_usize;
+        let mut chars = name.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                _ if depth > 0 => {}
+                _ => {
+                    spaced.push(c);
+                    if c == '.' && chars.peek().is_some_and(|next| next.is_alphabetic()) {
+                        spaced.push(' ');
+                    }
+                }
+            }
+        }
+        let name = spaced.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        let (name, suffix) = match NAME_SUFFIXES.iter().find_map(|suffix| {
+            name.strip_suffix(suffix)
+                .and_then(|rest| rest.strip_suffix(' '))
+                .map(|rest| (rest.trim_end_matches(',').trim_end(), *suffix))
+        }) {
+            Some((rest, suffix)) if !rest.is_empty() => (rest.to_string(), Some(suffix)),
+            _ => (name, None),
+        };
+        if name.is_empty() {
+            return None;
+        }
+
+        let (first, last) = match name.split_once(',') {
+            Some((last, first)) if !first.contains(',') => (first.trim(), last.trim()),
+            // Lists of names, or organizations
+            Some(_) => ("", name.as_str()),
+            None => split_last_name(&name),
+        };
+        let suffix = suffix
+            .map(|suffix| format!(" {suffix}"))
+            .unwrap_or_default();
+
+        Some(if first.is_empty() || last.is_empty() {
+            Self {
+                name: format!("{name}{suffix}"),
+                sort: format!("{name}{suffix}"),
+            }
+        } else {
+            Self {
+                name: format!("{first} {last}{suffix}"),
+                sort: format!("{last}, {first}{suffix}"),
+            }
+        })
+    }
+}
+
+/// Splits a name written `First Last` in its first and last names, the last name keeping its
+/// particles
+/// * Example: `Ursula K. Le Guin` gives `Ursula K.` and `Le Guin`, and `De Gaulle` has no first
+///   name
+fn split_last_name(name: &str) -> (&str, &str) {
+    let words: Vec<&str> = name.split(' ').collect();
+
+    let mut last = words.len() - 1;
+    while last > 0
+        && SURNAME_PARTICLES
+            .iter()
+            .any(|particle| words[last - 1].eq_ignore_ascii_case(particle))
+    {
+        last -= 1;
+    }
+
+    // Words are separated by a single space
+    let start: usize = words[..last].iter().map(|word| word.len() + 1).sum();
+    (name[..start].trim_end(), &name[start..])
+}
+
+// Sort forms are guessed for most sources, so authors are the same when their names are
+impl PartialEq for Author {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Hash for Author {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+/// Parses the authors of a book, separated by `;` or `&`
+///
+/// Authors written `Unknown`, like Calibre does for the books without author, are left out.
+/// * Example: `Knight, Phil;Smith, John` gives `Phil Knight` and `John Smith`
+pub fn parse_authors(authors: &str) -> Vec<Author> {
+    authors
+        .split([';', '&'])
+        .filter_map(Author::parse)
+        .filter(|author| {
+            !author.name.eq_ignore_ascii_case("unknown")
+                && !author.name.eq_ignore_ascii_case(UNKNOWN_AUTHOR)
+        })
+        .collect()
+}
+
+/// Returns the names of authors joined with ` & `, like `Phil Knight & John Smith`
+pub fn author_names(authors: &[Author]) -> String {
+    authors
+        .iter()
+        .map(|author| author.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" & ")
+}
+
+/// Returns the names of authors to display, `UNKNOWN_AUTHOR` if there are none
+fn display_author(authors: &[Author]) -> String {
+    if authors.is_empty() {
+        UNKNOWN_AUTHOR.to_string()
+    } else {
+        author_names(authors)
+    }
+}
+
+impl Clip {
+    /// Returns the names of the authors, see `author_names`, or `UNKNOWN_AUTHOR` if the clip has
+    /// none
+    pub fn author(&self) -> String {
+        display_author(&self.authors)
+    }
+
+    /// Returns a stable identifier for the clip, derived from its book, authors and location
+    ///
+    /// The identifier does not change between runs or program versions, so it can be used to
+    /// reference a clip from outside of the program. The authors are taken as written by the
+    /// source, as normalizing them would change the identifier of the clips already synced.
+    /// # Returns
+    /// * `String` - The clip identifier
+    ///   * Example: `clip-0a011cf28715b6c7`
+    pub fn id(&self) -> String {
+        // FNV-1a, as the std hashers are not guaranteed to be stable across releases
+        let mut identity = vec![
+            self.book.clone(),
+            self.source_author.clone(),
+            self.location.0.to_string(),
+            self.location.1.to_string(),
+        ];
+
+        // Highlights identity predates clip kinds, so they are left out of it to stay stable
+        if self.kind != ClipKind::Highlight {
+            identity.push(format!("{:?}", self.kind));
+        }
+
+        let hash = identity
+            .join("\u{1f}")
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            });
+
+        format!("clip-{hash:016x}")
+    }
 }
 
 /// Parses a Kindle clippings file into a vector of `BookClips`
@@ -47,6 +427,15 @@ pub struct Clip {
 /// let books_clips = clippings::parse_clips(clippings_text);
 /// ```
 pub fn parse_clips(input: &str) -> Vec<BookClips> {
+    group_clips(parse_clip_list(input))
+}
+
+/// Parses a Kindle clippings file into the list of its clips, in the file order
+/// # Variables
+/// * `input` - The input string to parse
+/// # Returns
+/// * `Vec<Clip>` - The parsed clips, not grouped by book
+pub fn parse_clip_list(input: &str) -> Vec<Clip> {
     // We use ==========\n========== to mark previously finished parsing jobs
     // So we split on this marker and take everything after it
     let input = input
@@ -54,22 +443,327 @@ pub fn parse_clips(input: &str) -> Vec<BookClips> {
         .last()
         .unwrap_or_else(|| unreachable!("A string is always splittable"));
 
-    // We parse all the clips with nom
-    let (_, clips) =
-        separated_list0(tuple((tag("=========="), line_ending)), nom_single_clip)(input)
-            .expect("Could not parse clippings");
+    // Clips are parsed one by one, so a malformed clip does not prevent parsing the others
+    let mut clips = Vec::new();
+    for entry in split_entries(input) {
+        // Skipping the BOM character and the line jumps left by the upload marker
+        let entry = entry.trim_start_matches(|c: char| c == '\u{feff}' || c.is_whitespace());
+        if entry.is_empty() {
+            continue;
+        }
+
+        match nom_single_clip(entry) {
+            Ok((_, clip)) => clips.push(clip),
+            Err(_) => eprintln!(
+                "Skipping malformed clipping {:?}",
+                entry.lines().next().unwrap_or_default()
+            ),
+        }
+    }
 
-    // We group clips by book and author
     clips
-        .group_by(|a, b| a.book == b.book && a.author == b.author)
-        .map(|clips| BookClips {
-            book_name: clips[0].book.clone(),
-            author: clips[0].author.clone(),
-            clips: Vec::from(clips),
-        })
+}
+
+/// Splits a clippings file into its clips, separated by `==========` lines
+fn split_entries(input: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+
+    for line in input.split_inclusive('\n') {
+        if line.trim_end() == "==========" {
+            entries.push(&input[start..end]);
+            start = end + line.len();
+        }
+        end += line.len();
+    }
+    entries.push(&input[start..]);
+
+    entries
+}
+
+/// Groups clips by book and author, whatever source they come from, and removes duplicates
+///
+/// Books are kept in the order of their first clip. Clips with the same identifier are the same
+/// clip saved again, like an edited note, so only the last one is kept. Highlights contained in
+/// a longer highlight of the same book are dropped too, as Kindle keeps the old highlight when it
+/// gets extended.
+///
+/// The same clip read from several sources, like the clippings file and a notebook export, is
+/// merged into the first one read, which gains the chapter, color, page and progress it lacks.
+/// # Variables
+/// * `clips` - The clips to group, in the order they were taken
+/// # Returns
+/// * `Vec<BookClips>` - The clips grouped by book
+pub fn group_clips(clips: Vec<Clip>) -> Vec<BookClips> {
+    group_enriched_clips(clips, Vec::new())
+}
+
+/// Groups clips by book like `group_clips`, merging in the clips of the sources which only
+/// enrich the others, see `ClippingSource::enriches_only`
+///
+/// The enriching clips matching none of the clips are dropped, as their book name, location or
+/// date can differ from the clips they would otherwise duplicate.
+/// # Variables
+/// * `clips` - The clips to group, in the order they were taken
+/// * `enrichments` - The clips only enriching the others
+/// # Returns
+/// * `Vec<BookClips>` - The clips grouped by book
+pub fn group_enriched_clips(clips: Vec<Clip>, enrichments: Vec<Clip>) -> Vec<BookClips> {
+    let mut books_clips: Vec<BookClips> = Vec::new();
+
+    let enrichments_start = clips.len();
+    for clip in merge_clips(
+        clips.into_iter().chain(enrichments).collect(),
+        enrichments_start,
+    ) {
+        match books_clips
+            .iter_mut()
+            .find(|book| book.book_name == clip.book && book.authors == clip.authors)
+        {
+            Some(book) => book.clips.push(clip),
+            None => books_clips.push(BookClips {
+                book_name: clip.book.clone(),
+                authors: clip.authors.clone(),
+                clips: vec![clip],
+                metadata: None,
+            }),
+        }
+    }
+
+    for book in &mut books_clips {
+        book.dedupe_clips();
+    }
+
+    books_clips
+}
+
+/// Merges the clips read from several sources, see `group_clips`, dropping the clips from
+/// `enrichments_start` on which match no previous clip
+///
+/// Sources name books differently, like `Shoe Dog` and `Shoe Dog: A Memoir`, so the highlights
+/// and notes with the same text at the same location are matched first. Their books then take
+/// the name and author of the first source, and bookmarks are matched within the same book.
+/// Clips without text, like the highlights of Kindle sidecars, take the name and author of the
+/// book with the same title instead.
+fn merge_clips(clips: Vec<Clip>, enrichments_start: usize) -> Vec<Clip> {
+    type BookKey = (String, Vec<Author>);
+
+    // Book and authors of the first source, by book and authors of the other sources
+    let mut renames: HashMap<BookKey, (BookKey, String)> = HashMap::new();
+    let mut index = ClipIndex::default();
+    for clip in &clips {
+        let text = normalize_whitespace(&clip.content);
+        let first = index.find(&clips, clip, &text, |other| {
+            (other.book != clip.book || other.authors != clip.authors)
+                && ((!text.is_empty() && !other.truncated)
+                    || is_same_title(&other.book, &clip.book))
+        });
+        if let Some(first) = first.map(|idx| &clips[idx]) {
+            let to = (first.book.clone(), first.authors.clone());
+            renames
+                .entry((clip.book.clone(), clip.authors.clone()))
+                .or_insert_with(|| (to, first.source_author.clone()));
+        }
+        index.insert(clip, text);
+    }
+
+    let mut merged: Vec<Clip> = Vec::new();
+    let mut index = ClipIndex::default();
+    for (clip_idx, mut clip) in clips.into_iter().enumerate() {
+        if let Some(((book, authors), source_author)) =
+            renames.get(&(clip.book.clone(), clip.authors.clone()))
+        {
+            clip.book = book.clone();
+            clip.authors = authors.clone();
+            clip.source_author = source_author.clone();
+        }
+
+        let text = normalize_whitespace(&clip.content);
+        let same = index.find(&merged, &clip, &text, |other| {
+            other.book == clip.book && other.authors == clip.authors
+        });
+        match same {
+            Some(idx) => {
+                let first = &mut merged[idx];
+                // Clips matched by date may be located differently, see `is_same_clip`
+                if first.location.0 == clip.location.0 && !clip.approximate_location {
+                    first.location.1 = first.location.1.max(clip.location.1);
+                }
+                first.page = first.page.or(clip.page);
+                first.chapter = first.chapter.take().or(clip.chapter);
+                first.progress = first.progress.or(clip.progress);
+                first.color = first.color.take().or(clip.color);
+                // The text of truncated clips is recovered from the other sources
+                if first.truncated && !text.is_empty() {
+                    first.content = clip.content;
+                    first.truncated = false;
+                    index.texts[idx] = text;
+                }
+            }
+            None if clip_idx >= enrichments_start => {}
+            None => {
+                index.insert(&clip, text);
+                merged.push(clip);
+            }
+        }
+    }
+
+    merged
+}
+
+/// Clips indexed by location and date, to find the same clip read from another source
+#[derive(Default)]
+struct ClipIndex {
+    // Text of the indexed clips, see `normalize_whitespace`
+    texts: Vec<String>,
+    by_location: HashMap<(ClipKind, usize), Vec<usize>>,
+    by_date: HashMap<(ClipKind, DateTime<Local>), Vec<usize>>,
+}
+
+impl ClipIndex {
+    /// Indexes the next clip, whose text is normalized with `normalize_whitespace`
+    fn insert(&mut self, clip: &Clip, text: String) {
+        let idx = self.texts.len();
+        self.texts.push(text);
+        self.by_location
+            .entry((clip.kind, clip.location.0))
+            .or_default()
+            .push(idx);
+        self.by_date
+            .entry((clip.kind, clip.date))
+            .or_default()
+            .push(idx);
+    }
+
+    /// Returns the position of the first indexed clip which is the same as the clip, see
+    /// `is_same_clip`, and is accepted by the filter
+    fn find(
+        &self,
+        clips: &[Clip],
+        clip: &Clip,
+        text: &str,
+        filter: impl Fn(&Clip) -> bool,
+    ) -> Option<usize> {
+        let at_location = self.by_location.get(&(clip.kind, clip.location.0));
+        let at_date = self.by_date.get(&(clip.kind, clip.date));
+
+        at_location
+            .into_iter()
+            .chain(at_date)
+            .flatten()
+            .copied()
+            .filter(|&idx| {
+                let first = &clips[idx];
+                is_same_normalized_clip(first, &self.texts[idx], clip, text) && filter(first)
+            })
+            .min()
+    }
+}
+
+/// Returns whether a clip read from another source is the same as a clip read first
+///
+/// Clips with text match on their location and text. Clips without text, and truncated clips,
+/// match on their location, or on their date to the second for the sources whose locations are
+/// approximated. Clips with an approximate location only match on their date.
+pub(crate) fn is_same_clip(first: &Clip, clip: &Clip) -> bool {
+    is_same_normalized_clip(
+        first,
+        &normalize_whitespace(&first.content),
+        clip,
+        &normalize_whitespace(&clip.content),
+    )
+}
+
+/// Returns whether a clip is the same as a clip read first, see `is_same_clip`, given their text
+/// collapsed by `normalize_whitespace`
+fn is_same_normalized_clip(first: &Clip, first_text: &str, clip: &Clip, text: &str) -> bool {
+    if first.kind != clip.kind {
+        return false;
+    }
+
+    // Approximate locations can be the location of a nearby clip
+    let same_place = if first.approximate_location || clip.approximate_location {
+        first.date == clip.date
+    } else {
+        first.location.0 == clip.location.0
+    };
+
+    // Truncated clips have no text to compare, see `Clip::truncated`
+    if text.is_empty() || first.truncated {
+        same_place || first.date == clip.date
+    } else {
+        same_place && first_text == text
+    }
+}
+
+/// Returns whether two book names are the same title, ignoring case and punctuation
+///
+/// A name can be the start of the other, as file names get truncated.
+/// * Example: `Shoe Dog: A Memoir by the Creator of Nike` and `Shoe Dog_ A Memoir` are the same
+pub(crate) fn is_same_title(a: &str, b: &str) -> bool {
+    let (a, b) = (title_letters(a), title_letters(b));
+
+    !a.is_empty() && !b.is_empty() && (a.starts_with(&b) || b.starts_with(&a))
+}
+
+/// Returns the entries whose title matches a book name best, in their order
+///
+/// Titles equal to the name, or to its title with or without subtitle, ignoring case and
+/// punctuation, are preferred. Otherwise the titles matching it with `is_same_title` are only
+/// returned if they are all the same, so `Dune` does not get the file of `Dune Messiah`.
+pub(crate) fn matching_titles<'a, T>(
+    entries: &'a [(String, T)],
+    book_name: &str,
+) -> Vec<&'a (String, T)> {
+    let title = BookTitle::parse(book_name);
+    let names: Vec<String> = [
+        book_name.to_string(),
+        title.format(TitleStyle::Title),
+        title.format(TitleStyle::TitleSubtitle),
+    ]
+    .iter()
+    .map(|name| title_letters(name))
+    .filter(|name| !name.is_empty())
+    .collect();
+
+    let exact: Vec<&(String, T)> = entries
+        .iter()
+        .filter(|(title, _)| names.contains(&title_letters(title)))
+        .collect();
+    if !exact.is_empty() {
+        return exact;
+    }
+
+    let similar: Vec<&(String, T)> = entries
+        .iter()
+        .filter(|(title, _)| is_same_title(title, book_name))
+        .collect();
+    let is_single = similar
+        .windows(2)
+        .all(|pair| title_letters(&pair[0].0) == title_letters(&pair[1].0));
+
+    if is_single {
+        similar
+    } else {
+        Vec::new()
+    }
+}
+
+/// Returns the letters and digits of a title, in lower case
+fn title_letters(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
         .collect()
 }
 
+/// Collapses whitespace, which sources do not keep the same way
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 /// Uses nom to parse a single clip, delimited by `==========`
 /// # Variables
 /// * `input` - The input string to parse
@@ -85,65 +779,119 @@ pub fn parse_clips(input: &str) -> Vec<BookClips> {
 /// # Errors
 /// * `IResult::Error` - If the input cannot be parsed
 fn nom_single_clip(input: &str) -> IResult<&str, Clip> {
-    let (input, ((book, author), (location_start, location_end), _, date, content)) = tuple((
+    let (input, ((book, author), (kind, page, location, date), _, content)) = tuple((
         // Book name and author
         nom_first_row,
-        // Location
-        nom_location_2023_02,
-        // Removing the " | Added on " part
-        take_until(", "),
-        // Date
-        map(
-            delimited(
-                tag(", "),
-                not_line_ending,
-                tuple((line_ending, line_ending)),
-            ),
-            parse_date,
-        ),
-        // Content
-        terminated(not_line_ending, line_ending),
+        // Clip kind, page, location and date
+        map_parser(terminated(not_line_ending, line_ending), nom_second_row),
+        // Empty line
+        line_ending,
+        // Content, which is the rest of the clip
+        rest,
     ))(input)?;
 
+    // Keeping the location of the clips past the clipping limit, but not their placeholder
+    let content = content.trim_end_matches(['\r', '\n']);
+    let truncated = is_clipping_limit(content);
+
     Ok((
         input,
         Clip {
             book,
-            author: author.replace(')', ""),
-            content: content.to_string(),
+            authors: parse_authors(author),
+            source_author: author.to_string(),
+            kind,
+            content: if truncated { "" } else { content }.to_string(),
             date,
-            location: (
-                location_start.parse().expect("Not a valid integer"),
-                location_end.parse().expect("Not a valid integer"),
-            ),
+            location,
+            page,
+            chapter: None,
+            progress: None,
+            color: None,
+            truncated,
+            approximate_location: false,
         },
     ))
 }
 
+/// Returns whether the content of a clip is the placeholder of the clipping limit
+/// * Example: `<You have reached the clipping limit for this item>`
+fn is_clipping_limit(content: &str) -> bool {
+    let content = content.trim();
+
+    content.starts_with('<')
+        && content.ends_with('>')
+        && content.to_lowercase().contains(CLIPPING_LIMIT_PLACEHOLDER)
+}
+
+/// Uses nom to parse the second row of a clip, which contains its kind, page, location and date
+/// # Variables
+/// * `input` - The second row to parse
+///   * Example: `- Your Highlight on page 58 | location 877-879 | Added on Monday, 13 February 2023 00:29:40`
+/// # Returns
+/// * `IResult<&str, (ClipKind, Option<usize>, (usize, usize), DateTime<Local>)>` - Input remainder + The parsed clip kind, page, start and end location and date
+/// # Errors
+/// * `IResult::Error` - If the input cannot be parsed, like a date in another language or a
+///   location too large to be a number
+#[allow(clippy::type_complexity)]
+fn nom_second_row(
+    input: &str,
+) -> IResult<&str, (ClipKind, Option<usize>, (usize, usize), DateTime<Local>)> {
+    tuple((
+        // Kind
+        preceded(
+            tag("- Your "),
+            alt((
+                value(ClipKind::Highlight, tag("Highlight")),
+                value(ClipKind::Note, tag("Note")),
+                value(ClipKind::Bookmark, tag("Bookmark")),
+            )),
+        ),
+        // Page, which is not always a number
+        opt(preceded(
+            tuple((take_until("page "), tag("page "))),
+            map_res(digit1, str::parse),
+        )),
+        // Location
+        map_res(
+            nom_location_2023_02,
+            |(start, end): (&str, &str)| -> Result<(usize, usize), ParseIntError> {
+                Ok((start.parse()?, end.parse()?))
+            },
+        ),
+        // Removing the " Added on Monday" part, then parsing the date
+        preceded(tuple((take_until(", "), tag(", "))), nom_date),
+    ))(input)
+}
+
 /// Uses nom to parse the first row of a clip, which contains the book name and the author
+///
+/// The author is in the last parentheses of the row, which can be nested like
+/// `(John Smith (Ed.))`. Rows without them, like the ones of personal documents and articles
+/// sent to the Kindle, are the book name and have no author.
 /// # Variables
 /// * `input` - The input string to parse
 ///    * Example: `The Lord of the Rings (J. R. R. Tolkien)`
 /// # Returns
-/// * `IResult<&str, (String, &str)>` - Input remainder + The parsed book name and author
+/// * `IResult<&str, (String, &str)>` - Input remainder + The parsed book name and author, empty
+///   if the row has none
 /// # Errors
-/// * `IResult::Error` - If the input cannot be parsed
+/// * `IResult::Error` - If the row is empty
 pub fn nom_first_row(input: &str) -> IResult<&str, (String, &str)> {
-    let (input, (book, author)) = many_till(
-        take(1_usize),
-        delimited(tag(" ("), take_until(")"), tuple((tag(")"), line_ending))),
+    let (input, row) = verify(
+        map(terminated(not_line_ending, line_ending), |row: &str| {
+            // Removing BOM character if present
+            row.trim_start_matches('\u{feff}').trim()
+        }),
+        |row: &str| !row.is_empty(),
     )(input)?;
 
-    Ok((
-        input,
-        (
-            book.iter()
-                // Removing BOM character and line jumps if present
-                .filter(|x| **x != '\u{feff}'.to_string() && **x != '\n'.to_string())
-                .fold(String::new(), |acc, x| acc + x),
-            author,
-        ),
-    ))
+    let (book, author) = match trailing_group(row) {
+        Some((book, author)) if !book.trim().is_empty() => (book.trim(), author),
+        _ => (row, ""),
+    };
+
+    Ok((input, (book.to_string(), author)))
 }
 
 /// Uses nom to parse the location of a clip
@@ -169,34 +917,39 @@ pub fn nom_location_old(input: &str) -> IResult<&str, (&str, &str)> {
 /// # Variables
 /// * `input` - The input string to parse
 ///  * Example: - Your Highlight at location 1502-1507 |
+///  * Example: - Your Note on page 58 | Location 879 |
 /// # Returns
-/// * `IResult<&str, (&str, &str)>` - Input remainder + The parsed start and end location
+/// * `IResult<&str, (&str, &str)>` - Input remainder + The parsed start and end location, which are equal for single locations
 /// # Errors
 /// * `IResult::Error` - If the input cannot be parsed
 pub fn nom_location_2023_02(input: &str) -> IResult<&str, (&str, &str)> {
     // Removing the page
-    let (input, _) = take_until("location")(input)?;
+    let (input, _) = alt((take_until("location "), take_until("Location ")))(input)?;
 
-    let (input, (location_start, _, location_end)) = delimited(
-        tag("location "),
-        tuple((digit1, take(1usize), digit1)),
+    let (input, (location_start, location_end)) = delimited(
+        tag_no_case("location "),
+        tuple((digit1, opt(preceded(take(1usize), digit1)))),
         tag(" |"),
     )(input)?;
 
-    Ok((input, (location_start, location_end)))
+    Ok((
+        input,
+        (location_start, location_end.unwrap_or(location_start)),
+    ))
 }
 
-/// Parses a date from the format `1 January 2021 00:00:00`
+/// Uses nom to parse a date from the format `1 January 2021 00:00:00`
 /// # Variables
 /// * `input` - The input string to parse
 ///   * Example: `1 January 2021 00:00:00`
 /// # Returns
-/// * `IResult<&str, String>` - Input remainder + The parsed date in the format `YYYY-MM-DD`
-/// # Note
-fn parse_date(input: &str) -> DateTime<Local> {
-    Local
-        .datetime_from_str(input, "%e %B %Y %H:%M:%S")
-        .expect("Cannot parse input")
+/// * `IResult<&str, DateTime<Local>>` - Input remainder + The parsed date, in the local timezone
+/// # Errors
+/// * `IResult::Error` - If the date cannot be parsed, like a date in another language
+fn nom_date(input: &str) -> IResult<&str, DateTime<Local>> {
+    map_res(not_line_ending, |date| {
+        Local.datetime_from_str(date, "%e %B %Y %H:%M:%S")
+    })(input)
 }
 
 #[cfg(test)]
@@ -212,12 +965,70 @@ mod tests {
     fn test_parse_date() {
         let test_date = "1 December 2020 16:58:58";
 
-        let parsed_date = parse_date(test_date);
+        let (_, parsed_date) = nom_date(test_date).expect("Could not nom date");
 
         assert_eq!(
             parsed_date,
             Local.with_ymd_and_hms(2020, 12, 1, 16, 58, 58).unwrap()
         );
+        assert!(nom_date("1 décembre 2020 16:58:58").is_err());
+    }
+
+    #[test]
+    fn test_clip_id_survives_serialization() {
+        let clip = parse_clip_list(&get_test_clippings()).remove(0);
+
+        let serialized = serde_json::to_string(&clip).expect("Could not serialize clip");
+        let deserialized: Clip =
+            serde_json::from_str(&serialized).expect("Could not deserialize clip");
+
+        // The id hashes the author as written by the source, which has to be kept
+        assert_eq!(deserialized.id(), clip.id());
+    }
+
+    #[test]
+    fn test_parse_authors() {
+        let names = |authors: &str| -> Vec<(String, String)> {
+            parse_authors(authors)
+                .into_iter()
+                .map(|author| (author.name, author.sort))
+                .collect()
+        };
+        let name = |name: &str, sort: &str| (name.to_string(), sort.to_string());
+
+        assert_eq!(
+            names("Tolkien, J. R. R."),
+            vec![name("J. R. R. Tolkien", "Tolkien, J. R. R.")]
+        );
+        assert_eq!(names("J.R.R. Tolkien"), names("Tolkien, J. R. R."));
+        assert_eq!(
+            names("Knight, Phil;Smith, John"),
+            vec![
+                name("Phil Knight", "Knight, Phil"),
+                name("John Smith", "Smith, John")
+            ]
+        );
+        assert_eq!(
+            names("Ursula K. Le Guin & Martin Luther King, Jr."),
+            vec![
+                name("Ursula K. Le Guin", "Le Guin, Ursula K."),
+                name("Martin Luther King Jr.", "King, Martin Luther Jr.")
+            ]
+        );
+        assert_eq!(
+            names("Ludwig van der Rohe"),
+            vec![name("Ludwig van der Rohe", "van der Rohe, Ludwig")]
+        );
+        assert_eq!(names("De Gaulle"), vec![name("De Gaulle", "De Gaulle")]);
+        assert_eq!(names("Plato; "), vec![name("Plato", "Plato")]);
+        assert_eq!(names(""), vec![]);
+        assert_eq!(names("Unknown"), vec![]);
+
+        // Authors with the same name are the same, whatever their sort form
+        assert_eq!(
+            parse_authors("Le Guin, Ursula K."),
+            parse_authors("Ursula K. Le Guin")
+        );
     }
 
     #[test]
@@ -230,6 +1041,96 @@ mod tests {
         assert_eq!(book, "Tiago Forte");
     }
 
+    #[test]
+    fn test_parse_first_row_without_author() {
+        let (_, (book, author)) =
+            nom_first_row("\u{feff}Why We Sleep - The Atlantic\r\n").expect("Could not nom row");
+        assert_eq!((book.as_str(), author), ("Why We Sleep - The Atlantic", ""));
+
+        // Nested parentheses are part of the author, whose role is then dropped
+        let (_, (book, author)) =
+            nom_first_row("Essays (Vol. 2) (John Smith (Ed.))\n").expect("Could not nom row");
+        assert_eq!(
+            (book.as_str(), author),
+            ("Essays (Vol. 2)", "John Smith (Ed.)")
+        );
+        assert_eq!(author_names(&parse_authors(author)), "John Smith");
+
+        // A row made of parentheses only is the book name
+        let (_, (book, author)) = nom_first_row("(Untitled (1))\n").expect("Could not nom row");
+        assert_eq!((book.as_str(), author), ("(Untitled (1))", ""));
+
+        assert!(nom_first_row("\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_malformed_clippings() {
+        let input = "Why We Sleep - The Atlantic
+- Your Highlight on Location 12-13 | Added on Monday, 13 February 2023 00:29:40
+
+Sleep is the single most effective thing we can do.
+==========
+report.pdf (Unknown)
+- Your Highlight on page 3 | Location 30-31 | Added on Monday, 13 February 2023 00:29:45
+
+Written by nobody
+==========
+Not a clip at all
+==========
+Shoe Dog (Phil Knight)
+- Your Highlight on Location 877-879 | Added on Monday, 13 February 2023 00:30:00
+
+So that's what I did.
+Over two lines.
+==========
+";
+        let books_clips = parse_clips(input);
+
+        // The malformed clips are skipped without losing the next ones
+        let books: Vec<(&str, String)> = books_clips
+            .iter()
+            .map(|book| (book.book_name.as_str(), book.author()))
+            .collect();
+        assert_eq!(
+            books,
+            vec![
+                ("Why We Sleep - The Atlantic", UNKNOWN_AUTHOR.to_string()),
+                ("report.pdf", UNKNOWN_AUTHOR.to_string()),
+                ("Shoe Dog", "Phil Knight".to_string())
+            ]
+        );
+        assert!(books_clips[1].authors.is_empty());
+        assert_eq!(
+            books_clips[2].clips[0].content,
+            "So that's what I did.\nOver two lines."
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_date_and_location() {
+        let input = "Shoe Dog (Phil Knight)
+- Your Highlight on Location 12-13 | Added on Monday, 13 Februar 2023 00:29:40
+
+Written with a date in another language
+==========
+Shoe Dog (Phil Knight)
+- Your Highlight on Location 99999999999999999999999-99999999999999999999999 | Added on Monday, 13 February 2023 00:29:45
+
+Written past the last location
+==========
+Shoe Dog (Phil Knight)
+- Your Highlight on Location 877-879 | Added on Monday, 13 February 2023 00:30:00
+
+So that's what I did.
+==========
+";
+        let clips = parse_clip_list(input);
+
+        // The clips which cannot be parsed are skipped instead of stopping the parsing
+        let contents: Vec<&str> = clips.iter().map(|clip| clip.content.as_str()).collect();
+        assert_eq!(contents, vec!["So that's what I did."]);
+    }
+
     #[test]
     fn test_parse_single_clip_simple() {
         let test_clip = "How to Win Friends and Influence People (Dale Carnegie)
@@ -265,6 +1166,25 @@ People reflexively assume that competition is always a good thing, that it alway
         insta::assert_yaml_snapshot!(parsed_clip);
     }
 
+    #[test]
+    fn test_clip_id() {
+        let test_clip = "Shoe Dog (Phil Knight)
+- Your Highlight on page 58 | location 877-879 | Added on Monday, 13 February 2023 00:29:40
+
+You must forget your limits.
+";
+        let (_, parsed_clip) = nom_single_clip(test_clip).expect("Could not nom clip");
+
+        // The identifier must stay stable as it is written in exported files
+        assert_eq!(parsed_clip.id(), "clip-0a011cf28715b6c7");
+
+        // Authors are identified as written, not by their normalized names
+        let (_, sorted_clip) = nom_single_clip(&test_clip.replace("Phil Knight", "Knight, Phil"))
+            .expect("Could not nom clip");
+        assert_eq!(sorted_clip.authors, parsed_clip.authors);
+        assert_ne!(sorted_clip.id(), parsed_clip.id());
+    }
+
     #[test]
     fn test_parse_location_2023_02() {
         let test_location = "- Your Highlight on page 58 | location 877-879 |";
@@ -274,6 +1194,144 @@ People reflexively assume that competition is always a good thing, that it alway
         insta::assert_yaml_snapshot!(parsed_location);
     }
 
+    #[test]
+    fn test_parse_single_note() {
+        let test_clip = "Shoe Dog (Phil Knight)
+- Your Note on page 58 | Location 879 | Added on Monday, 13 February 2023 00:30:12
+
+Forgetting as a skill
+";
+        let (_, parsed_clip) = nom_single_clip(test_clip).expect("Could not nom note");
+
+        assert_eq!(parsed_clip.kind, ClipKind::Note);
+        assert_eq!(parsed_clip.location, (879, 879));
+        assert_eq!(parsed_clip.page, Some(58));
+        assert_eq!(parsed_clip.content, "Forgetting as a skill");
+    }
+
+    #[test]
+    fn test_parse_single_bookmark() {
+        let test_clip = "Shoe Dog (Phil Knight)
+- Your Bookmark on page xii | location 170 | Added on Monday, 13 February 2023 00:31:00
+
+
+";
+        let (_, parsed_clip) = nom_single_clip(test_clip).expect("Could not nom bookmark");
+
+        assert_eq!(parsed_clip.kind, ClipKind::Bookmark);
+        assert_eq!(parsed_clip.location, (170, 170));
+        assert_eq!(parsed_clip.page, None);
+        assert_eq!(parsed_clip.content, "");
+    }
+
+    #[test]
+    fn test_parse_clipping_limit() {
+        let input = "Shoe Dog (Phil Knight)
+- Your Highlight on page 58 | location 877-879 | Added on Monday, 13 February 2023 00:29:40
+
+You must forget your limits.
+==========
+Shoe Dog (Phil Knight)
+- Your Highlight on page 60 | location 901-903 | Added on Monday, 13 February 2023 00:31:00
+
+ <You have reached the clipping limit for this item>
+==========
+";
+        let clips = parse_clip_list(input);
+
+        // The location is kept but not the placeholder
+        assert!(!clips[0].truncated);
+        assert!(clips[1].truncated);
+        assert_eq!(clips[1].content, "");
+        assert_eq!(clips[1].location, (901, 903));
+
+        let books_clips = group_clips(clips.clone());
+        assert_eq!(books_clips[0].truncated_clips().len(), 1);
+        assert_eq!(books_clips[0].annotated_highlights().len(), 1);
+
+        // The text read from another source, like a notebook export, replaces the placeholder
+        let mut recovered = clips[1].clone();
+        recovered.content = "Let everyone else call your idea crazy.".to_string();
+        recovered.truncated = false;
+        let books_clips = group_clips(clips.into_iter().chain([recovered]).collect());
+        assert_eq!(books_clips[0].clips.len(), 2);
+        assert!(books_clips[0].truncated_clips().is_empty());
+        assert_eq!(
+            books_clips[0].clips[1].content,
+            "Let everyone else call your idea crazy."
+        );
+    }
+
+    #[test]
+    fn test_annotated_highlights() {
+        let input = get_test_clippings();
+        let parsed_clippings = parse_clips(input.as_str());
+        let shoe_dog = parsed_clippings.last().unwrap();
+
+        let annotated = shoe_dog.annotated_highlights();
+
+        // The bookmark is skipped and the note is attached to its highlight
+        assert_eq!(annotated.len(), 1);
+        assert_eq!(annotated[0].0.kind, ClipKind::Highlight);
+        assert_eq!(annotated[0].1.unwrap().content, "Forgetting as a skill");
+    }
+
+    #[test]
+    fn test_group_clips() {
+        let input = "Shoe Dog (Phil Knight)
+- Your Highlight on Location 877-878 | Added on Monday, 13 February 2023 00:29:40
+
+So that's what I
+==========
+Other Book (Someone)
+- Your Note on Location 10 | Added on Monday, 13 February 2023 00:29:50
+
+First note
+==========
+Shoe Dog (Phil Knight)
+- Your Highlight on Location 877-879 | Added on Monday, 13 February 2023 00:30:00
+
+So that's what I did.
+==========
+Other Book (Someone)
+- Your Note on Location 10 | Added on Monday, 13 February 2023 00:30:10
+
+Edited note
+";
+        let books_clips = group_clips(parse_clip_list(input));
+
+        // Books are grouped even when their clips are not contiguous
+        assert_eq!(books_clips.len(), 2);
+        assert_eq!(books_clips[0].book_name, "Shoe Dog");
+
+        // The extended highlight replaces the first one
+        assert_eq!(books_clips[0].clips.len(), 1);
+        assert_eq!(books_clips[0].clips[0].location, (877, 879));
+
+        // The edited note replaces the first one
+        assert_eq!(books_clips[1].clips.len(), 1);
+        assert_eq!(books_clips[1].clips[0].content, "Edited note");
+    }
+
+    #[test]
+    fn test_group_clips_keeps_truncated_highlights() {
+        let input = "Shoe Dog (Phil Knight)
+- Your Highlight on Location 877-879 | Added on Monday, 13 February 2023 00:29:40
+
+So that's what I did.
+==========
+Shoe Dog (Phil Knight)
+- Your Highlight on Location 878-878 | Added on Monday, 13 February 2023 00:30:00
+
+<You have reached the clipping limit for this item>
+";
+        let books_clips = group_clips(parse_clip_list(input));
+
+        // A highlight without text is not contained in the longer highlight around it
+        assert_eq!(books_clips[0].clips.len(), 2);
+        assert!(books_clips[0].clips[1].truncated);
+    }
+
     #[test]
     fn test_all_clippings_parsing() {
         let input = get_test_clippings();
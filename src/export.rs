@@ -0,0 +1,194 @@
+use crate::clippings::BookClips;
+use crate::title::TitleStyle;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+pub mod anki;
+pub mod csv;
+pub mod epub;
+pub mod html;
+pub mod json;
+pub mod logseq;
+pub mod markdown;
+pub mod obsidian;
+pub mod org;
+
+/// Characters that are not allowed in file names on at least one of the major platforms
+const FORBIDDEN_FILENAME_CHARACTERS: [char; 9] = ['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Maximum length of a generated file name, without its extension
+const MAX_FILENAME_LENGTH: usize = 150;
+
+/// File name template of the exports writing one file per book, see `render_filename`
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{title}";
+
+/// Renders a file name from a template and makes it safe to use on any file system
+/// # Variables
+/// * `template` - The template to render
+///   * `{title}` is replaced by the book name, in the given style
+///   * `{subtitle}` is replaced by the book subtitle, empty if it has none
+///   * `{author}` is replaced by the book author
+/// * `book` - The book to render the template for
+/// * `title_style` - How the book name is written, see `BookTitle::format`
+/// # Returns
+/// * `String` - The sanitized file name, without extension
+pub fn render_filename(template: &str, book: &BookClips, title_style: TitleStyle) -> String {
+    let title = book.title();
+
+    sanitize_filename(
+        &template
+            .replace("{title}", &title.format(title_style))
+            .replace("{subtitle}", title.subtitle.as_deref().unwrap_or_default())
+            .replace("{author}", &book.author()),
+    )
+}
+
+/// Formats a clip location the way Kindle displays it
+/// # Variables
+/// * `location` - The start and end location of the clip
+/// # Returns
+/// * `String` - The formatted location
+///   * Example: `1502-1507`, or `879` for single locations
+pub fn format_location(location: (usize, usize)) -> String {
+    if location.0 == location.1 {
+        location.0.to_string()
+    } else {
+        format!("{}-{}", location.0, location.1)
+    }
+}
+
+/// Copies a book cover in the `covers` folder of an export
+/// # Variables
+/// * `cover` - The cover image, see `BookCovers`
+/// * `output_dir` - The export directory
+/// * `file_name` - The file name of the book, without extension
+/// # Returns
+/// * `Result<String>` - The path of the copy, relative to the export directory
+///   * Example: `covers/Shoe Dog.jpg`
+/// # Errors
+/// Raise on file system errors
+pub(crate) fn copy_cover(cover: &Path, output_dir: &Path, file_name: &str) -> Result<String> {
+    let extension = cover.extension().unwrap_or_default().to_string_lossy();
+    let cover_path = format!("covers/{file_name}.{extension}");
+
+    fs::create_dir_all(output_dir.join("covers"))?;
+    fs::copy(cover, output_dir.join(&cover_path))?;
+
+    Ok(cover_path)
+}
+
+/// Escapes text to be used in HTML or XML documents
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Makes a string safe to use as a file name
+/// # Variables
+/// * `name` - The string to sanitize
+///   * Example: `Building a Second Brain: A Proven Method`
+/// # Returns
+/// * `String` - The sanitized file name
+///   * Example: `Building a Second Brain - A Proven Method`
+pub fn sanitize_filename(name: &str) -> String {
+    let sanitized = name
+        // `:` is usually a subtitle separator so we keep the separation visible
+        .replace(':', " -")
+        .chars()
+        .filter(|c| !c.is_control() && !FORBIDDEN_FILENAME_CHARACTERS.contains(c))
+        .collect::<String>()
+        // Collapsing whitespace
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // Leading and trailing dots create hidden or invalid files on some systems
+    let sanitized: String = sanitized
+        .trim_matches(|c: char| c == '.' || c.is_whitespace())
+        .chars()
+        .take(MAX_FILENAME_LENGTH)
+        .collect();
+
+    if sanitized.is_empty() {
+        "untitled".to_string()
+    } else {
+        sanitized.trim_end().to_string()
+    }
+}
+
+/// Makes file names unique by appending a counter to the ones that were already used
+/// # Variables
+/// * `name` - The file name to deduplicate
+/// * `used_names` - The file names already used, which gets updated with the returned name
+/// # Returns
+/// * `String` - The unique file name
+///   * Example: `Shoe Dog (2)` if `Shoe Dog` was already used
+pub fn unique_filename(name: String, used_names: &mut HashSet<String>) -> String {
+    let mut unique_name = name.clone();
+    let mut counter = 2;
+
+    // File systems are often case-insensitive so we compare lowercase names
+    while !used_names.insert(unique_name.to_lowercase()) {
+        unique_name = format!("{name} ({counter})");
+        counter += 1;
+    }
+
+    unique_name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(
+            sanitize_filename("Building a Second Brain: A Proven Method"),
+            "Building a Second Brain - A Proven Method"
+        );
+        assert_eq!(sanitize_filename("AC/DC? <Live>\n"), "ACDC Live");
+        assert_eq!(sanitize_filename("..."), "untitled");
+    }
+
+    #[test]
+    fn test_render_filename() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = crate::clippings::parse_clips(&input);
+        let book = books_clips
+            .iter()
+            .find(|book| book.book_name.starts_with("Building a Second Brain"))
+            .unwrap();
+
+        assert_eq!(
+            render_filename("{title} - {author}", book, TitleStyle::Title),
+            "Building a Second Brain - Tiago Forte"
+        );
+        assert_eq!(
+            render_filename("{subtitle}", book, TitleStyle::Full),
+            "A Proven Method to Organize Your Digital Life and Unlock Your Creative Potential"
+        );
+    }
+
+    #[test]
+    fn test_unique_filename() {
+        let mut used_names = HashSet::new();
+
+        assert_eq!(
+            unique_filename("Shoe Dog".to_string(), &mut used_names),
+            "Shoe Dog"
+        );
+        assert_eq!(
+            unique_filename("shoe dog".to_string(), &mut used_names),
+            "shoe dog (2)"
+        );
+        assert_eq!(
+            unique_filename("Shoe Dog".to_string(), &mut used_names),
+            "Shoe Dog (3)"
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use crate::clippings::{BookClips, Clip};
+use crate::export::{escape_html, format_location};
+use crate::title::TitleStyle;
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+/// Name of the note type, whose identifier is derived from it
+const MODEL_NAME: &str = "Kindle highlight";
+
+/// Fields of the note type, in order
+const MODEL_FIELDS: [&str; 5] = ["Quote", "Book", "Author", "Location", "Note"];
+
+const CARD_FRONT: &str = "<blockquote>{{Quote}}</blockquote>";
+
+const CARD_BACK: &str = "{{FrontSide}}
+<hr id=answer>
+<b>{{Book}}</b><br>{{Author}}<br><small>Location {{Location}}</small>
+{{#Note}}<p><i>{{Note}}</i></p>{{/Note}}";
+
+const CARD_CSS: &str = ".card { font-family: Georgia, serif; font-size: 20px; text-align: center; color: black; background-color: white; }
+blockquote { text-align: left; }";
+
+/// Schema of an Anki collection, version 11, which every Anki version can import
+const COLLECTION_SCHEMA: &str = "
+CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null, scm integer not null, ver integer not null, dty integer not null, usn integer not null, ls integer not null, conf text not null, models text not null, decks text not null, dconf text not null, tags text not null);
+CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null, mod integer not null, usn integer not null, tags text not null, flds text not null, sfld integer not null, csum integer not null, flags integer not null, data text not null);
+CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null, ord integer not null, mod integer not null, usn integer not null, type integer not null, queue integer not null, due integer not null, ivl integer not null, factor integer not null, reps integer not null, lapses integer not null, left integer not null, odue integer not null, odid integer not null, flags integer not null, data text not null);
+CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null, ease integer not null, ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null, type integer not null);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+/// Number of collections written by the process, to give them unique temporary file names
+static COLLECTIONS_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Options for the Anki export
+#[derive(Debug, Clone)]
+pub struct AnkiOptions {
+    /// Name of the deck the cards are imported in, whose identifier is derived from it
+    pub deck_name: String,
+    /// How the book names are written, see `BookTitle::format`
+    pub title_style: TitleStyle,
+}
+
+impl Default for AnkiOptions {
+    fn default() -> Self {
+        Self {
+            deck_name: "Kindle highlights".to_string(),
+            title_style: TitleStyle::Full,
+        }
+    }
+}
+
+/// Writes the highlights as an Anki deck package, one note per highlight
+///
+/// Notes identifiers are derived from the clips identifiers, so importing a newer export of
+/// the same clips updates the existing cards instead of duplicating them.
+///
+/// # Arguments
+///
+/// * `output_file` - The `.apkg` file to write
+/// * `books_clips` - The list of book clips to export
+/// * `options` - The export options
+///
+/// # Errors
+/// Raise on file system and SQLite errors
+pub fn export_clips(
+    output_file: &Path,
+    books_clips: &[BookClips],
+    options: &AnkiOptions,
+) -> Result<()> {
+    // SQLite needs a file to write the collection to before it gets zipped
+    let collection_path = std::env::temp_dir().join(format!(
+        "kindle_to_notion-{}-{}.anki2",
+        process::id(),
+        COLLECTIONS_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let result = write_collection(&collection_path, books_clips, options).and_then(|_| {
+        let collection = fs::read(&collection_path)?;
+
+        let mut package = ZipWriter::new(fs::File::create(output_file)?);
+        package.start_file("collection.anki2", FileOptions::default())?;
+        package.write_all(&collection)?;
+        // No media is exported, but Anki expects the media mapping to exist
+        package.start_file("media", FileOptions::default())?;
+        package.write_all(b"{}")?;
+        package.finish()?;
+
+        Ok(())
+    });
+
+    fs::remove_file(&collection_path).ok();
+
+    result
+}
+
+/// Writes the Anki collection SQLite database
+fn write_collection(path: &Path, books_clips: &[BookClips], options: &AnkiOptions) -> Result<()> {
+    let connection = Connection::open(path)?;
+    connection.execute_batch(COLLECTION_SCHEMA)?;
+
+    let now = Utc::now();
+    let model_id = stable_id(MODEL_NAME);
+    let deck_id = stable_id(&options.deck_name);
+
+    connection.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            now.timestamp(),
+            now.timestamp_millis(),
+            collection_conf(deck_id).to_string(),
+            json!({ model_id.to_string(): model(model_id, deck_id, now.timestamp()) }).to_string(),
+            json!({
+                "1": deck(1, "Default", now.timestamp()),
+                deck_id.to_string(): deck(deck_id, &options.deck_name, now.timestamp()),
+            })
+            .to_string(),
+            json!({ "1": deck_conf() }).to_string(),
+        ],
+    )?;
+
+    let mut due = 0;
+    let mut note_ids = HashSet::new();
+    for book in books_clips {
+        let title = book.display_name(options.title_style);
+        for (clip, note) in book.annotated_highlights() {
+            // The same clip can be found in several books, like a book exported twice
+            let note_id = stable_id(&clip.id());
+            if !note_ids.insert(note_id) {
+                continue;
+            }
+
+            let fields = [
+                html_escape(&clip.content),
+                html_escape(&title),
+                html_escape(&book.author()),
+                format_location(clip.location),
+                note.map(|note| html_escape(&note.content))
+                    .unwrap_or_default(),
+            ];
+
+            connection.execute(
+                "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ' kindle ', ?5, ?6, ?7, 0, '')",
+                params![
+                    note_id,
+                    note_guid(clip),
+                    model_id,
+                    now.timestamp(),
+                    fields.join("\u{1f}"),
+                    clip.content,
+                    field_checksum(&clip.content),
+                ],
+            )?;
+
+            // New cards, shown in reading order
+            due += 1;
+            connection.execute(
+                "INSERT INTO cards VALUES (?1, ?2, ?3, 0, ?4, -1, 0, 0, ?5, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+                params![note_id, note_id, deck_id, now.timestamp(), due],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the GUID of the note of a clip, which Anki uses to match notes on import
+fn note_guid(clip: &Clip) -> String {
+    format!("kindle-{}", clip.id())
+}
+
+/// Derives a positive 53 bits identifier from a name, to stay exact in Anki's JSON
+fn stable_id(name: &str) -> i64 {
+    let digest = Sha1::digest(name.as_bytes());
+    let bytes: [u8; 8] = digest[..8].try_into().unwrap_or_else(|_| unreachable!());
+
+    (u64::from_be_bytes(bytes) >> 11) as i64
+}
+
+/// Computes the checksum Anki uses to find duplicate notes, from the text of the first field
+/// without its HTML
+fn field_checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+    let bytes: [u8; 4] = digest[..4].try_into().unwrap_or_else(|_| unreachable!());
+
+    i64::from(u32::from_be_bytes(bytes))
+}
+
+/// Escapes text to be used in the HTML fields of Anki notes
+fn html_escape(text: &str) -> String {
+    escape_html(text).replace('\n', "<br>")
+}
+
+fn collection_conf(deck_id: i64) -> serde_json::Value {
+    json!({
+        "activeDecks": [deck_id],
+        "curDeck": deck_id,
+        "newSpread": 0,
+        "collapseTime": 1200,
+        "timeLim": 0,
+        "estTimes": true,
+        "dueCounts": true,
+        "curModel": null,
+        "nextPos": 1,
+        "sortType": "noteFld",
+        "sortBackwards": false,
+        "addToCur": true,
+    })
+}
+
+fn model(model_id: i64, deck_id: i64, modified: i64) -> serde_json::Value {
+    json!({
+        "id": model_id,
+        "name": MODEL_NAME,
+        "type": 0,
+        "mod": modified,
+        "usn": -1,
+        "sortf": 0,
+        "did": deck_id,
+        "tmpls": [{
+            "name": "Highlight",
+            "ord": 0,
+            "qfmt": CARD_FRONT,
+            "afmt": CARD_BACK,
+            "did": null,
+            "bqfmt": "",
+            "bafmt": "",
+        }],
+        "flds": MODEL_FIELDS.iter().enumerate().map(|(ord, name)| json!({
+            "name": name,
+            "ord": ord,
+            "sticky": false,
+            "rtl": false,
+            "font": "Arial",
+            "size": 20,
+            "media": [],
+        })).collect::<Vec<_>>(),
+        "css": CARD_CSS,
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "tags": [],
+        "vers": [],
+        // The card is generated when the quote is not empty
+        "req": [[0, "all", [0]]],
+    })
+}
+
+fn deck(deck_id: i64, name: &str, modified: i64) -> serde_json::Value {
+    json!({
+        "id": deck_id,
+        "name": name,
+        "desc": "",
+        "mod": modified,
+        "usn": -1,
+        "dyn": 0,
+        "conf": 1,
+        "collapsed": false,
+        "extendNew": 10,
+        "extendRev": 50,
+        "newToday": [0, 0],
+        "revToday": [0, 0],
+        "lrnToday": [0, 0],
+        "timeToday": [0, 0],
+    })
+}
+
+fn deck_conf() -> serde_json::Value {
+    json!({
+        "id": 1,
+        "name": "Default",
+        "mod": 0,
+        "usn": 0,
+        "maxTaken": 60,
+        "autoplay": true,
+        "timer": 0,
+        "replayq": true,
+        "dyn": false,
+        "new": {
+            "bury": true,
+            "delays": [1, 10],
+            "initialFactor": 2500,
+            "ints": [1, 4, 7],
+            "order": 1,
+            "perDay": 20,
+            "separate": true,
+        },
+        "rev": {
+            "bury": true,
+            "ease4": 1.3,
+            "fuzz": 0.05,
+            "ivlFct": 1,
+            "maxIvl": 36500,
+            "minSpace": 1,
+            "perDay": 100,
+        },
+        "lapse": {
+            "delays": [10],
+            "leechAction": 0,
+            "leechFails": 8,
+            "minInt": 1,
+            "mult": 0,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use crate::source::tests::create_fixture_directory;
+    use std::io::Read;
+
+    /// Exports the test clippings and returns the GUIDs and fields of the notes
+    fn export_test_clippings(name: &str) -> Vec<(String, String)> {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+
+        export_test_notes(name, &parse_clips(input.as_str()))
+            .into_iter()
+            .map(|(guid, fields, _, _)| (guid, fields))
+            .collect()
+    }
+
+    /// Exports book clips and returns the GUID, fields, sort field and checksum of the notes
+    fn export_test_notes(
+        name: &str,
+        books_clips: &[BookClips],
+    ) -> Vec<(String, String, String, i64)> {
+        let output_dir = create_fixture_directory("anki");
+        let package_path = output_dir.join(format!("{name}.apkg"));
+        export_clips(&package_path, books_clips, &AnkiOptions::default())
+            .expect("Could not export");
+
+        // Extracting the collection from the package
+        let mut package = zip::ZipArchive::new(fs::File::open(&package_path).unwrap()).unwrap();
+        let mut collection = Vec::new();
+        package
+            .by_name("collection.anki2")
+            .unwrap()
+            .read_to_end(&mut collection)
+            .unwrap();
+        let collection_path = output_dir.join(format!("{name}.anki2"));
+        fs::write(&collection_path, collection).unwrap();
+
+        let connection = Connection::open(&collection_path).unwrap();
+        let mut statement = connection
+            .prepare("SELECT guid, flds, sfld, csum FROM notes ORDER BY id")
+            .unwrap();
+        let notes = statement
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        fs::remove_dir_all(&output_dir).ok();
+
+        notes
+    }
+
+    #[test]
+    fn test_export_clips() {
+        let notes = export_test_clippings("first");
+
+        // The note is attached to its highlight and the bookmark is skipped
+        assert_eq!(notes.len(), 5);
+        assert!(notes
+            .iter()
+            .any(|(_, fields)| fields.ends_with("\u{1f}877-879\u{1f}Forgetting as a skill")));
+
+        // Exporting again keeps the same GUIDs so Anki updates the notes
+        assert_eq!(notes, export_test_clippings("second"));
+    }
+
+    #[test]
+    fn test_export_clips_skips_duplicates() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let mut books_clips = parse_clips(input.as_str());
+        books_clips.extend(parse_clips(input.as_str()));
+
+        let package_path = std::env::temp_dir().join(format!(
+            "kindle_to_notion-test-{}-duplicates.apkg",
+            process::id()
+        ));
+        let result = export_clips(&package_path, &books_clips, &AnkiOptions::default());
+        fs::remove_file(&package_path).ok();
+
+        assert!(result.is_ok(), "{result:?}");
+    }
+
+    #[test]
+    fn test_export_clips_checksums_raw_text() {
+        let input = "Shoe Dog (Phil Knight)
+- Your Highlight on page 58 | Location 877-879 | Added on Monday, 13 February 2023 00:29:40
+
+Nike & Blue Ribbon <Sports>
+==========
+";
+        let notes = export_test_notes("escaped", &parse_clips(input));
+
+        // The fields are HTML, but Anki finds duplicates from their text
+        let (_, fields, sort_field, checksum) = &notes[0];
+        assert!(fields.starts_with("Nike &amp; Blue Ribbon &lt;Sports&gt;\u{1f}"));
+        assert_eq!(sort_field, "Nike & Blue Ribbon <Sports>");
+        assert_eq!(*checksum, field_checksum("Nike & Blue Ribbon <Sports>"));
+    }
+}
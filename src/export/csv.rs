@@ -0,0 +1,186 @@
+use crate::clippings::{author_names, BookClips, ClipKind};
+use crate::source::vocabulary::VocabLookup;
+use crate::title::TitleStyle;
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+/// Date format expected by Readwise
+const READWISE_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Columns layout of the CSV export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvLayout {
+    /// Columns of the Readwise CSV import
+    Readwise,
+    /// Every clip field, for spreadsheets
+    Generic,
+}
+
+/// Writes the highlights as CSV, one highlight per row with its note
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the CSV, for example a file or stdout
+/// * `books_clips` - The list of book clips to export
+/// * `layout` - The columns layout to use
+/// * `title_style` - How the book titles are written, see `BookTitle::format`
+///
+/// # Errors
+/// Raise on IO errors
+pub fn write_csv<W: Write>(
+    writer: W,
+    books_clips: &[BookClips],
+    layout: CsvLayout,
+    title_style: TitleStyle,
+) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+
+    for book in books_clips {
+        let title = book.display_name(title_style);
+        let author = author_names(&book.authors);
+        for (clip, note) in book.annotated_highlights() {
+            let note = note.map_or("", |note| note.content.as_str());
+
+            match layout {
+                CsvLayout::Readwise => writer.serialize(ReadwiseRow {
+                    highlight: &clip.content,
+                    title: &title,
+                    author: &author,
+                    url: "",
+                    note,
+                    location: clip.location.0,
+                    location_type: "location",
+                    date: clip.date.format(READWISE_DATE_FORMAT).to_string(),
+                })?,
+                CsvLayout::Generic => writer.serialize(GenericRow {
+                    id: clip.id(),
+                    title: &title,
+                    author: &author,
+                    kind: clip.kind,
+                    content: &clip.content,
+                    note,
+                    location_start: clip.location.0,
+                    location_end: clip.location.1,
+                    page: clip.page,
+                    date: clip.date.to_rfc3339(),
+                })?,
+            }
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the vocabulary lookups as CSV, one lookup per row
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the CSV, for example a file or stdout
+/// * `lookups` - The lookups to export
+///
+/// # Errors
+/// Raise on IO errors
+pub fn write_vocabulary_csv<W: Write>(writer: W, lookups: &[VocabLookup]) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+
+    for lookup in lookups {
+        writer.serialize(VocabularyRow {
+            word: &lookup.word,
+            stem: &lookup.stem,
+            language: &lookup.language,
+            usage: &lookup.usage,
+            title: &lookup.book,
+            author: &lookup.author,
+            date: lookup.date.to_rfc3339(),
+        })?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct ReadwiseRow<'a> {
+    highlight: &'a str,
+    title: &'a str,
+    author: &'a str,
+    #[serde(rename = "URL")]
+    url: &'a str,
+    note: &'a str,
+    location: usize,
+    #[serde(rename = "Location Type")]
+    location_type: &'a str,
+    date: String,
+}
+
+#[derive(Debug, Serialize)]
+struct GenericRow<'a> {
+    id: String,
+    title: &'a str,
+    author: &'a str,
+    kind: ClipKind,
+    content: &'a str,
+    note: &'a str,
+    location_start: usize,
+    location_end: usize,
+    page: Option<usize>,
+    date: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VocabularyRow<'a> {
+    word: &'a str,
+    stem: &'a str,
+    language: &'a str,
+    usage: &'a str,
+    title: &'a str,
+    author: &'a str,
+    date: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use crate::source::tests::{local_text_dates, utc_text_dates};
+    use std::fs;
+
+    fn export_test_clippings(layout: CsvLayout) -> String {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+
+        let mut output = Vec::new();
+        write_csv(&mut output, &books_clips, layout, TitleStyle::Full)
+            .expect("Could not write CSV");
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_write_readwise_csv() {
+        let output = export_test_clippings(CsvLayout::Readwise);
+
+        assert!(output.starts_with("Highlight,Title,Author,URL,Note,Location,Location Type,Date\n"));
+        insta::assert_snapshot!(output);
+    }
+
+    #[test]
+    fn test_write_generic_csv() {
+        insta::assert_snapshot!(local_text_dates(&export_test_clippings(CsvLayout::Generic)));
+    }
+
+    #[test]
+    fn test_write_vocabulary_csv() {
+        let lookups = crate::source::vocabulary::tests::get_test_lookups();
+
+        let mut output = Vec::new();
+        write_vocabulary_csv(&mut output, &lookups).expect("Could not write CSV");
+
+        insta::assert_snapshot!(utc_text_dates(&String::from_utf8(output).unwrap()));
+    }
+}
@@ -0,0 +1,390 @@
+use crate::clippings::BookClips;
+use crate::export::escape_html;
+use crate::export::html::html_quote;
+use crate::source::cover::BookCovers;
+use crate::title::TitleStyle;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+const STYLE: &str = "body { font-family: serif; line-height: 1.5; }
+h1 { font-size: 1.4em; }
+.author { font-style: italic; }
+blockquote { margin: 1.5em 0; padding-left: 1em; border-left: 3px solid #999; }
+blockquote .note { font-style: italic; }
+blockquote footer { font-size: 0.8em; color: #555; }
+.cover { text-align: center; }
+.cover img { max-height: 12em; }
+";
+
+/// Options for the EPUB export
+#[derive(Debug, Clone)]
+pub struct EpubOptions {
+    /// Title of the EPUB book
+    pub title: String,
+    /// Covers of the books, shown at the start of their chapter
+    pub covers: BookCovers,
+    /// How book names are written in chapter titles and the table of contents
+    pub title_style: TitleStyle,
+}
+
+impl Default for EpubOptions {
+    fn default() -> Self {
+        Self {
+            title: "Kindle highlights".to_string(),
+            covers: BookCovers::default(),
+            title_style: TitleStyle::Full,
+        }
+    }
+}
+
+/// Compiles the highlights into an EPUB 3 book, one chapter per book
+///
+/// Books without highlights are skipped, so filtering `books_clips` beforehand gives an EPUB of
+/// a subset of the highlights.
+///
+/// # Arguments
+///
+/// * `output_file` - The `.epub` file to write
+/// * `books_clips` - The list of book clips to export
+/// * `options` - The export options
+///
+/// # Errors
+/// Raise on file system errors, or if there are no highlights to export
+pub fn export_clips(
+    output_file: &Path,
+    books_clips: &[BookClips],
+    options: &EpubOptions,
+) -> Result<()> {
+    let books: Vec<&BookClips> = books_clips
+        .iter()
+        .filter(|book| !book.annotated_highlights().is_empty())
+        .collect();
+
+    if books.is_empty() {
+        bail!("No highlights to export");
+    }
+
+    let mut epub = ZipWriter::new(fs::File::create(output_file)?);
+
+    // The mimetype has to be the first file of the archive, uncompressed
+    epub.start_file(
+        "mimetype",
+        FileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    epub.write_all(b"application/epub+zip")?;
+
+    epub.start_file("META-INF/container.xml", FileOptions::default())?;
+    epub.write_all(CONTAINER.as_bytes())?;
+
+    epub.start_file("OEBPS/content.opf", FileOptions::default())?;
+    epub.write_all(package_document(&books, options).as_bytes())?;
+
+    epub.start_file("OEBPS/nav.xhtml", FileOptions::default())?;
+    epub.write_all(navigation_document(&books, options).as_bytes())?;
+
+    epub.start_file("OEBPS/style.css", FileOptions::default())?;
+    epub.write_all(STYLE.as_bytes())?;
+
+    for (idx, book) in books.iter().enumerate() {
+        println!("Exporting clips from {:?}", book.book_name);
+
+        let cover = options.covers.cover_of(book);
+        if let Some(cover) = cover {
+            // Images are already compressed
+            epub.start_file(
+                format!("OEBPS/{}", cover_file_name(idx, cover)),
+                FileOptions::default().compression_method(CompressionMethod::Stored),
+            )?;
+            epub.write_all(&fs::read(cover)?)?;
+        }
+
+        epub.start_file(chapter_file_name(idx), FileOptions::default())?;
+        epub.write_all(
+            book.to_epub_chapter(
+                cover.map(|cover| cover_file_name(idx, cover)).as_deref(),
+                options.title_style,
+            )
+            .as_bytes(),
+        )?;
+    }
+
+    epub.finish()?;
+
+    Ok(())
+}
+
+/// Returns the path of a chapter, relative to the root of the archive
+fn chapter_file_name(idx: usize) -> String {
+    format!("OEBPS/chapter-{}.xhtml", idx + 1)
+}
+
+/// Returns the path of a book cover, relative to the package document
+fn cover_file_name(idx: usize, cover: &Path) -> String {
+    let extension = cover.extension().unwrap_or_default().to_string_lossy();
+    format!("images/cover-{}.{}", idx + 1, extension.to_lowercase())
+}
+
+/// Returns the media type of an image, from its extension
+fn image_media_type(path: &str) -> &'static str {
+    match path.rsplit('.').next() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+/// Renders the package document, with the metadata, manifest and reading order
+fn package_document(books: &[&BookClips], options: &EpubOptions) -> String {
+    let clip_ids: Vec<String> = books
+        .iter()
+        .flat_map(|book| book.clips.iter().map(|clip| clip.id()))
+        .collect();
+
+    // The modification date is the last clip date, so exporting the same clips gives the same file
+    let modified: DateTime<Utc> = books
+        .iter()
+        .flat_map(|book| book.clips.iter().map(|clip| clip.date))
+        .max()
+        .map_or_else(Utc::now, |date| date.with_timezone(&Utc));
+
+    let mut manifest = String::new();
+    let mut spine = String::new();
+    for (idx, book) in books.iter().enumerate() {
+        if let Some(cover) = options.covers.cover_of(book) {
+            let cover = cover_file_name(idx, cover);
+            manifest.push_str(&format!(
+                "    <item id=\"cover-{}\" href=\"{cover}\" media-type=\"{}\"/>\n",
+                idx + 1,
+                image_media_type(&cover)
+            ));
+        }
+        manifest.push_str(&format!(
+            "    <item id=\"chapter-{0}\" href=\"chapter-{0}.xhtml\" media-type=\"application/xhtml+xml\"/>\n",
+            idx + 1
+        ));
+        spine.push_str(&format!("    <itemref idref=\"chapter-{}\"/>\n", idx + 1));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">urn:uuid:{}</dc:identifier>
+    <dc:title>{}</dc:title>
+    <dc:creator>kindle_to_notion</dc:creator>
+    <dc:language>en</dc:language>
+    <meta property="dcterms:modified">{}</meta>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="style" href="style.css" media-type="text/css"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+        uuid_from(&clip_ids.join(",")),
+        escape_html(&options.title),
+        modified.format("%Y-%m-%dT%H:%M:%SZ"),
+    )
+}
+
+/// Renders the navigation document, listing the chapters
+fn navigation_document(books: &[&BookClips], options: &EpubOptions) -> String {
+    let entries: String = books
+        .iter()
+        .enumerate()
+        .map(|(idx, book)| {
+            format!(
+                "      <li><a href=\"chapter-{}.xhtml\">{}</a></li>\n",
+                idx + 1,
+                escape_html(&book.display_name(options.title_style))
+            )
+        })
+        .collect();
+
+    xhtml_document(
+        &options.title,
+        &format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n    <h1>{}</h1>\n    <ol>\n{entries}    </ol>\n  </nav>\n",
+            escape_html(&options.title)
+        ),
+    )
+}
+
+// Creating a Book's clippings EPUB chapter
+impl BookClips {
+    /// # Arguments
+    ///
+    /// * `cover` - The path of the book cover, relative to the chapter
+    /// * `title_style` - How the book name is written
+    fn to_epub_chapter(&self, cover: Option<&str>, title_style: TitleStyle) -> String {
+        let title = self.display_name(title_style);
+        let mut body = format!(
+            "<h1>{}</h1>\n<p class=\"author\">{}</p>\n",
+            escape_html(&title),
+            escape_html(&self.author())
+        );
+        if let Some(cover) = cover {
+            body.push_str(&format!(
+                "<p class=\"cover\"><img src=\"{}\" alt=\"Cover\"/></p>\n",
+                escape_html(cover)
+            ));
+        }
+
+        for (clip, note) in self.annotated_highlights() {
+            body.push_str(&html_quote(clip, note));
+        }
+
+        xhtml_document(&title, &body)
+    }
+}
+
+/// Wraps a body in a XHTML document
+fn xhtml_document(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" lang="en" xml:lang="en">
+<head>
+  <meta charset="utf-8"/>
+  <title>{}</title>
+  <link rel="stylesheet" type="text/css" href="style.css"/>
+</head>
+<body>
+{body}</body>
+</html>
+"#,
+        escape_html(title)
+    )
+}
+
+/// Derives a name based UUID, formatted as a version 5 UUID
+fn uuid_from(name: &str) -> String {
+    let mut bytes: [u8; 16] = Sha1::digest(name.as_bytes())[..16]
+        .try_into()
+        .unwrap_or_else(|_| unreachable!());
+
+    // Version and variant bits
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use crate::source::cover::tests::get_test_covers;
+    use chrono::TimeZone;
+    use std::io::Read;
+
+    #[test]
+    fn test_export_clips() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+
+        let output_file =
+            std::env::temp_dir().join(format!("kindle_to_notion-test-{}.epub", std::process::id()));
+        let mut books_clips: Vec<BookClips> = books_clips
+            .into_iter()
+            .filter(|book| book.book_name.contains("Second Brain"))
+            .collect();
+
+        // The modification date is the last clip date, pinned so it does not depend on the
+        // timezone of the machine
+        let last_clip = books_clips[0].clips.last_mut().unwrap();
+        last_clip.date = Utc
+            .with_ymd_and_hms(2022, 12, 20, 12, 41, 55)
+            .unwrap()
+            .with_timezone(&chrono::Local);
+
+        export_clips(&output_file, &books_clips, &EpubOptions::default())
+            .expect("Could not export");
+
+        let mut epub = zip::ZipArchive::new(fs::File::open(&output_file).unwrap()).unwrap();
+        fs::remove_file(&output_file).ok();
+
+        // The mimetype is stored first and uncompressed
+        let mimetype = epub.by_index(0).unwrap();
+        assert_eq!(mimetype.name(), "mimetype");
+        assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        drop(mimetype);
+
+        // One chapter for the only book
+        assert_eq!(epub.len(), 6);
+
+        let mut package = String::new();
+        epub.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut package)
+            .unwrap();
+        insta::assert_snapshot!(package);
+    }
+
+    #[test]
+    fn test_export_clips_with_cover() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+        let (covers, covers_dir) = get_test_covers();
+
+        let output_file = covers_dir.join("highlights.epub");
+        let options = EpubOptions {
+            covers,
+            ..Default::default()
+        };
+        export_clips(&output_file, &books_clips, &options).expect("Could not export");
+
+        let mut epub = zip::ZipArchive::new(fs::File::open(&output_file).unwrap()).unwrap();
+        fs::remove_dir_all(&covers_dir).ok();
+
+        // Shoe Dog is the fourth book
+        let mut package = String::new();
+        epub.by_name("OEBPS/content.opf")
+            .unwrap()
+            .read_to_string(&mut package)
+            .unwrap();
+        assert!(package.contains(
+            "<item id=\"cover-4\" href=\"images/cover-4.jpg\" media-type=\"image/jpeg\"/>"
+        ));
+        assert!(epub.by_name("OEBPS/images/cover-4.jpg").is_ok());
+
+        let mut chapter = String::new();
+        epub.by_name("OEBPS/chapter-4.xhtml")
+            .unwrap()
+            .read_to_string(&mut chapter)
+            .unwrap();
+        assert!(chapter.contains("<img src=\"images/cover-4.jpg\" alt=\"Cover\"/>"));
+    }
+
+    #[test]
+    fn test_export_clips_without_highlights() {
+        let output_file = std::env::temp_dir().join("kindle_to_notion-never-written.epub");
+
+        assert!(export_clips(&output_file, &[], &EpubOptions::default()).is_err());
+        assert!(!output_file.exists());
+    }
+}
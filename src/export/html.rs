@@ -0,0 +1,415 @@
+use crate::clippings::{BookClips, Clip, ClipKind};
+use crate::export::{copy_cover, escape_html, format_location, unique_filename};
+use crate::source::cover::BookCovers;
+use crate::title::TitleStyle;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Date format used next to each quote and in the index
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Styles inlined in every page, so the site has no external assets
+const STYLE: &str = "
+body { font-family: Georgia, serif; max-width: 46rem; margin: 2rem auto; padding: 0 1rem; color: #222; background: #fdfcf8; line-height: 1.6; }
+a { color: #8a4b08; }
+header p, .meta { color: #666; font-size: 0.9rem; }
+input[type=search] { width: 100%; padding: 0.5rem; font-size: 1rem; box-sizing: border-box; }
+ul.books { list-style: none; padding: 0; }
+ul.books li { padding: 0.5rem 0; border-bottom: 1px solid #eee; }
+blockquote { margin: 1.5rem 0; padding: 0.5rem 1rem; border-left: 4px solid #d9b382; background: #fff; }
+blockquote p { margin: 0.5rem 0; }
+blockquote .note { font-style: italic; color: #444; }
+blockquote footer { color: #666; font-size: 0.85rem; }
+img.cover { float: right; max-width: 8rem; margin: 0 0 1rem 1rem; box-shadow: 0 1px 4px #0003; }
+";
+
+/// Client-side search over the index embedded in the index page
+const SEARCH_SCRIPT: &str = "
+const index = JSON.parse(document.getElementById('search-index').textContent);
+const input = document.getElementById('search');
+const books = document.getElementById('books');
+const results = document.getElementById('results');
+
+input.addEventListener('input', () => {
+  const query = input.value.trim().toLowerCase();
+  books.hidden = query.length > 0;
+  results.replaceChildren();
+  if (!query) return;
+
+  for (const entry of index) {
+    if (!entry.text.toLowerCase().includes(query) && !entry.book.toLowerCase().includes(query)) continue;
+    const item = document.createElement('li');
+    const link = document.createElement('a');
+    link.href = entry.href;
+    link.textContent = entry.book;
+    const text = document.createElement('p');
+    text.textContent = entry.text;
+    item.append(link, text);
+    results.append(item);
+  }
+});
+";
+
+/// Options for the HTML export
+#[derive(Debug, Clone)]
+pub struct HtmlOptions {
+    /// Title of the index page
+    pub site_title: String,
+    /// Covers of the books, copied in the `covers` folder of the site
+    pub covers: BookCovers,
+    /// How book names are written in the index, the search index and the book pages
+    pub title_style: TitleStyle,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        Self {
+            site_title: "Reading highlights".to_string(),
+            covers: BookCovers::default(),
+            title_style: TitleStyle::Full,
+        }
+    }
+}
+
+/// Writes a self-contained static HTML site, with an index page and one page per book
+///
+/// The index lists the books by last highlight date and embeds a search index of every clip,
+/// searched in the browser so the site can be served from any static host.
+///
+/// # Arguments
+///
+/// * `output_dir` - The directory where the site will be written, created if needed
+/// * `books_clips` - The list of book clips to export
+/// * `options` - The export options
+///
+/// # Returns
+/// The path of the index page
+///
+/// # Errors
+/// Raise on file system errors
+pub fn export_clips(
+    output_dir: &Path,
+    books_clips: &[BookClips],
+    options: &HtmlOptions,
+) -> Result<PathBuf> {
+    let books_dir = output_dir.join("books");
+    fs::create_dir_all(&books_dir)?;
+
+    let mut used_names = HashSet::new();
+    let mut index_entries = Vec::new();
+    let mut search_index = Vec::new();
+
+    for book in books_clips {
+        println!("Exporting clips from {:?}", book.book_name);
+
+        let file_name = unique_filename(slugify(&book.book_name), &mut used_names);
+        let href = format!("books/{file_name}.html");
+
+        let cover = match options.covers.cover_of(book) {
+            Some(cover) => Some(copy_cover(cover, output_dir, &file_name)?),
+            None => None,
+        };
+
+        fs::write(
+            books_dir.join(format!("{file_name}.html")),
+            book.to_html_page(options, cover.as_deref()),
+        )?;
+
+        let title = book.display_name(options.title_style);
+        let highlights = book.annotated_highlights();
+        for (clip, _) in &highlights {
+            search_index.push(SearchEntry {
+                book: title.clone(),
+                href: format!("{href}#{}", clip.id()),
+                text: &clip.content,
+            });
+        }
+
+        let last_date = highlights.iter().map(|(clip, _)| clip.date).max();
+        index_entries.push((book, href, last_date));
+    }
+
+    // Most recently read books first
+    index_entries.sort_by_key(|entry| std::cmp::Reverse(entry.2));
+
+    let index_path = output_dir.join("index.html");
+    fs::write(
+        &index_path,
+        index_page(&index_entries, &search_index, options)?,
+    )?;
+
+    Ok(index_path)
+}
+
+#[derive(Debug, Serialize)]
+struct SearchEntry<'a> {
+    book: String,
+    href: String,
+    text: &'a str,
+}
+
+/// Renders the index page, listing books and embedding the search index
+fn index_page(
+    books: &[(&BookClips, String, Option<DateTime<Local>>)],
+    search_index: &[SearchEntry],
+    options: &HtmlOptions,
+) -> Result<String> {
+    let mut body = String::new();
+
+    body.push_str(&format!(
+        "<header><h1>{}</h1><p>{} books</p></header>\n",
+        escape_html(&options.site_title),
+        books.len()
+    ));
+    body.push_str(
+        "<input type=\"search\" id=\"search\" placeholder=\"Search highlights\" autocomplete=\"off\">\n",
+    );
+    body.push_str("<ul class=\"books\" id=\"books\">\n");
+
+    for (book, href, last_date) in books {
+        body.push_str(&format!(
+            "<li><a href=\"{}\">{}</a><div class=\"meta\">{} · {} highlights{}</div></li>\n",
+            escape_html(href),
+            escape_html(&book.display_name(options.title_style)),
+            escape_html(&book.author()),
+            book.annotated_highlights()
+                .iter()
+                .filter(|(clip, _)| clip.kind == ClipKind::Highlight)
+                .count(),
+            last_date.map_or(String::new(), |date| format!(
+                " · last on {}",
+                date.format(DATE_FORMAT)
+            )),
+        ));
+    }
+
+    body.push_str("</ul>\n<ul class=\"books\" id=\"results\"></ul>\n");
+
+    // `</` would close the script tag early
+    let search_index = serde_json::to_string(search_index)?.replace("</", "<\\/");
+    body.push_str(&format!(
+        "<script type=\"application/json\" id=\"search-index\">{search_index}</script>\n"
+    ));
+    body.push_str(&format!("<script>{SEARCH_SCRIPT}</script>\n"));
+
+    Ok(html_document(&options.site_title, &body))
+}
+
+// Creating a Book's clippings HTML page
+impl BookClips {
+    /// # Arguments
+    ///
+    /// * `options` - The export options
+    /// * `cover` - The path of the book cover, relative to the site root
+    fn to_html_page(&self, options: &HtmlOptions, cover: Option<&str>) -> String {
+        let mut body = String::new();
+        let title = self.display_name(options.title_style);
+
+        // Book pages are in the `books` folder
+        let cover = cover.map_or(String::new(), |cover| {
+            format!(
+                "<img class=\"cover\" src=\"../{}\" alt=\"\">",
+                escape_html(cover)
+            )
+        });
+        body.push_str(&format!(
+            "<header><p><a href=\"../index.html\">{}</a></p>{cover}<h1>{}</h1><p>{}</p></header>\n",
+            escape_html(&options.site_title),
+            escape_html(&title),
+            escape_html(&self.author())
+        ));
+
+        for (clip, note) in self.annotated_highlights() {
+            body.push_str(&html_quote(clip, note));
+        }
+
+        html_document(&title, &body)
+    }
+}
+
+/// Renders a clip as a HTML blockquote, anchored by the clip identifier
+///
+/// The output is also valid XHTML, for EPUB chapters
+pub(crate) fn html_quote(clip: &Clip, note: Option<&Clip>) -> String {
+    let mut quote = format!("<blockquote id=\"{}\">\n", clip.id());
+
+    let class = if clip.kind == ClipKind::Note {
+        " class=\"note\""
+    } else {
+        ""
+    };
+    for paragraph in clip.content.lines() {
+        quote.push_str(&format!("<p{class}>{}</p>\n", escape_html(paragraph)));
+    }
+
+    if let Some(note) = note {
+        for paragraph in note.content.lines() {
+            quote.push_str(&format!(
+                "<p class=\"note\">{}</p>\n",
+                escape_html(paragraph)
+            ));
+        }
+    }
+
+    quote.push_str(&format!(
+        "<footer>Location {} · {}</footer>\n</blockquote>\n",
+        format_location(clip.location),
+        clip.date.format(DATE_FORMAT)
+    ));
+
+    quote
+}
+
+/// Wraps a page body in a complete HTML document with the inlined styles
+fn html_document(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{}</title>
+<style>{STYLE}</style>
+</head>
+<body>
+{body}</body>
+</html>
+",
+        escape_html(title)
+    )
+}
+
+/// Turns a book name into an URL friendly file name
+/// * Example: `Shoe Dog` becomes `shoe-dog`
+fn slugify(name: &str) -> String {
+    let slug = name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        "book".to_string()
+    } else {
+        slug.chars().take(80).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use crate::source::cover::tests::get_test_covers;
+    use crate::source::tests::create_fixture_directory;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Shoe Dog"), "shoe-dog");
+        assert_eq!(
+            slugify("Building a Second Brain: A Proven Method (2022)"),
+            "building-a-second-brain-a-proven-method-2022"
+        );
+        assert_eq!(slugify("???"), "book");
+    }
+
+    #[test]
+    fn test_export_clips() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+
+        let output_dir =
+            std::env::temp_dir().join(format!("kindle_to_notion-test-html-{}", std::process::id()));
+        let index_path = export_clips(&output_dir, &books_clips, &HtmlOptions::default())
+            .expect("Could not export");
+
+        let index = fs::read_to_string(index_path).unwrap();
+        let book_page = fs::read_to_string(output_dir.join("books/shoe-dog.html")).unwrap();
+        fs::remove_dir_all(&output_dir).ok();
+
+        // Books are sorted by last highlight, Shoe Dog being the most recent
+        let shoe_dog = index.find("books/shoe-dog.html").unwrap();
+        let lincoln = index
+            .find("books/how-to-win-friends-and-influence-people.html")
+            .unwrap();
+        assert!(shoe_dog < lincoln);
+
+        // No external assets
+        assert!(!index.contains("src=") && !index.contains("rel=\"stylesheet\""));
+
+        insta::assert_snapshot!(book_page);
+    }
+
+    #[test]
+    fn test_export_clips_with_cover() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+        let (covers, covers_dir) = get_test_covers();
+
+        let output_dir = covers_dir.join("site");
+        let options = HtmlOptions {
+            covers,
+            ..Default::default()
+        };
+        export_clips(&output_dir, &books_clips, &options).expect("Could not export");
+
+        let book_page = fs::read_to_string(output_dir.join("books/shoe-dog.html")).unwrap();
+        let cover_copied = output_dir.join("covers/shoe-dog.jpg").exists();
+        fs::remove_dir_all(&covers_dir).ok();
+
+        assert!(cover_copied);
+        assert!(book_page.contains("<img class=\"cover\" src=\"../covers/shoe-dog.jpg\" alt=\"\">"));
+    }
+
+    #[test]
+    fn test_export_clips_with_title_style() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+
+        let output_dir = create_fixture_directory("html-title-style");
+        let options = HtmlOptions {
+            title_style: TitleStyle::Title,
+            ..Default::default()
+        };
+        let index_path =
+            export_clips(&output_dir, &books_clips, &options).expect("Could not export");
+
+        let index = fs::read_to_string(index_path).unwrap();
+        fs::remove_dir_all(&output_dir).ok();
+
+        // Search results show the book the way its page does
+        assert!(index.contains(">Building a Second Brain</a>"));
+        assert!(index.contains("{\"book\":\"Building a Second Brain\","));
+        assert!(!index.contains("\"book\":\"Building a Second Brain:"));
+    }
+
+    #[test]
+    fn test_export_clips_counts_highlights_only() {
+        let input = "Shoe Dog (Phil Knight)
+- Your Highlight on Location 877-879 | Added on Monday, 13 February 2023 00:29:40
+
+You must forget your limits.
+==========
+Shoe Dog (Phil Knight)
+- Your Note on Location 1200 | Added on Monday, 13 February 2023 00:35:00
+
+A note on its own
+==========
+";
+        let books_clips = parse_clips(input);
+
+        let output_dir = create_fixture_directory("html-count");
+        let index_path = export_clips(&output_dir, &books_clips, &HtmlOptions::default())
+            .expect("Could not export");
+
+        let index = fs::read_to_string(index_path).unwrap();
+        fs::remove_dir_all(&output_dir).ok();
+
+        // The lone note is listed on the book page, but is not a highlight
+        assert!(index.contains("Phil Knight · 1 highlights"));
+    }
+}
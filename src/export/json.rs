@@ -0,0 +1,249 @@
+use crate::clippings::{author_names, Author, BookClips, BookMetadata, Clip, ClipKind};
+use crate::source::vocabulary::VocabLookup;
+use crate::title::TitleStyle;
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::io::Write;
+
+/// Version of the JSON export schema, documented in `docs/json_export.md`
+///
+/// It is bumped on every change that could break a consumer of the export
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Writes the book clips as a single JSON document
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the document, for example a file or stdout
+/// * `books_clips` - The list of book clips to export
+/// * `title_style` - How the book titles are written, see `BookTitle::format`
+///
+/// # Errors
+/// Raise on IO errors
+pub fn write_json<W: Write>(
+    mut writer: W,
+    books_clips: &[BookClips],
+    title_style: TitleStyle,
+) -> Result<()> {
+    let document = JsonDocument {
+        schema_version: SCHEMA_VERSION,
+        books: books_clips
+            .iter()
+            .map(|book| JsonBook::new(book, title_style))
+            .collect(),
+    };
+
+    serde_json::to_writer_pretty(&mut writer, &document)?;
+    writeln!(writer)?;
+
+    Ok(())
+}
+
+/// Writes the clips as newline delimited JSON, one clip with its book per line
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the lines, for example a file or stdout
+/// * `books_clips` - The list of book clips to export
+/// * `title_style` - How the book titles are written, see `BookTitle::format`
+///
+/// # Errors
+/// Raise on IO errors
+pub fn write_ndjson<W: Write>(
+    mut writer: W,
+    books_clips: &[BookClips],
+    title_style: TitleStyle,
+) -> Result<()> {
+    for book in books_clips {
+        let title = book.display_name(title_style);
+        for clip in &book.clips {
+            let line = JsonLine {
+                schema_version: SCHEMA_VERSION,
+                book: &title,
+                author: author_names(&book.authors),
+                authors: &book.authors,
+                clip: JsonClip::from(clip),
+            };
+
+            serde_json::to_writer(&mut writer, &line)?;
+            writeln!(writer)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the vocabulary lookups as newline delimited JSON, one lookup per line
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the lines, for example a file or stdout
+/// * `lookups` - The lookups to export
+///
+/// # Errors
+/// Raise on IO errors
+pub fn write_vocabulary_ndjson<W: Write>(mut writer: W, lookups: &[VocabLookup]) -> Result<()> {
+    for lookup in lookups {
+        let line = JsonVocabularyLine {
+            schema_version: SCHEMA_VERSION,
+            lookup,
+        };
+
+        serde_json::to_writer(&mut writer, &line)?;
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct JsonDocument<'a> {
+    schema_version: u32,
+    books: Vec<JsonBook<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonBook<'a> {
+    title: String,
+    author: String,
+    authors: &'a [Author],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a BookMetadata>,
+    clips: Vec<JsonClip<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonLine<'a> {
+    schema_version: u32,
+    book: &'a str,
+    author: String,
+    authors: &'a [Author],
+    #[serde(flatten)]
+    clip: JsonClip<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonVocabularyLine<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    lookup: &'a VocabLookup,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonClip<'a> {
+    id: String,
+    kind: ClipKind,
+    content: &'a str,
+    location: JsonLocation,
+    page: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chapter: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    progress: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<&'a str>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    date: DateTime<Local>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonLocation {
+    start: usize,
+    end: usize,
+}
+
+impl<'a> JsonBook<'a> {
+    fn new(book: &'a BookClips, title_style: TitleStyle) -> Self {
+        Self {
+            title: book.display_name(title_style),
+            author: author_names(&book.authors),
+            authors: &book.authors,
+            metadata: book.metadata.as_ref(),
+            clips: book.clips.iter().map(JsonClip::from).collect(),
+        }
+    }
+}
+
+impl<'a> From<&'a Clip> for JsonClip<'a> {
+    fn from(clip: &'a Clip) -> Self {
+        Self {
+            id: clip.id(),
+            kind: clip.kind,
+            content: &clip.content,
+            location: JsonLocation {
+                start: clip.location.0,
+                end: clip.location.1,
+            },
+            page: clip.page,
+            chapter: clip.chapter.as_deref(),
+            progress: clip.progress,
+            color: clip.color.as_deref(),
+            truncated: clip.truncated,
+            date: clip.date,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use crate::source::tests::{local_text_dates, utc_text_dates};
+    use std::fs;
+
+    fn get_test_books_clips() -> Vec<BookClips> {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        parse_clips(input.as_str())
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut output = Vec::new();
+        write_json(&mut output, &get_test_books_clips(), TitleStyle::Full)
+            .expect("Could not write JSON");
+
+        insta::assert_snapshot!(local_text_dates(&String::from_utf8(output).unwrap()));
+    }
+
+    #[test]
+    fn test_write_ndjson() {
+        let mut output = Vec::new();
+        write_ndjson(&mut output, &get_test_books_clips(), TitleStyle::Full)
+            .expect("Could not write NDJSON");
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 7);
+
+        insta::assert_snapshot!(local_text_dates(&output));
+    }
+
+    #[test]
+    fn test_write_ndjson_with_color() {
+        let mut books_clips = get_test_books_clips();
+        let shoe_dog = books_clips.last_mut().unwrap();
+        shoe_dog.clips[0].color = Some("yellow".to_string());
+
+        let mut output = Vec::new();
+        write_ndjson(&mut output, &books_clips[3..], TitleStyle::Full)
+            .expect("Could not write NDJSON");
+
+        // Only the clips whose color is known have one
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains(r#""page":58,"color":"yellow","date""#));
+        assert!(!lines[1].contains(r#""color""#));
+    }
+
+    #[test]
+    fn test_write_vocabulary_ndjson() {
+        let lookups = crate::source::vocabulary::tests::get_test_lookups();
+
+        let mut output = Vec::new();
+        write_vocabulary_ndjson(&mut output, &lookups).expect("Could not write NDJSON");
+
+        insta::assert_snapshot!(utc_text_dates(&String::from_utf8(output).unwrap()));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::clippings::{BookClips, Clip};
+use crate::export::{format_location, render_filename, unique_filename, DEFAULT_FILENAME_TEMPLATE};
+use crate::title::TitleStyle;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options for the Logseq export
+#[derive(Debug, Clone)]
+pub struct LogseqOptions {
+    /// Template used for page file names, see `export::render_filename`
+    pub filename_template: String,
+    /// Tags added to the `tags::` property of each page
+    pub tags: Vec<String>,
+    /// Date format of the journal pages clip dates link to, which should match the
+    /// `:journal/page-title-format` of the graph
+    pub journal_date_format: String,
+    /// How book names are written in page names
+    pub title_style: TitleStyle,
+}
+
+impl Default for LogseqOptions {
+    fn default() -> Self {
+        Self {
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            tags: vec!["kindle".to_string()],
+            journal_date_format: "%Y-%m-%d".to_string(),
+            title_style: TitleStyle::Full,
+        }
+    }
+}
+
+/// Writes one Logseq page per book in the `pages` folder of a graph
+///
+/// # Arguments
+///
+/// * `graph_dir` - The directory of the Logseq graph, its `pages` folder is created if needed
+/// * `books_clips` - The list of book clips to export
+/// * `options` - The export options
+///
+/// # Returns
+/// The paths of the written pages, in the same order as `books_clips`
+///
+/// # Errors
+/// Raise on file system errors
+pub fn export_clips(
+    graph_dir: &Path,
+    books_clips: &[BookClips],
+    options: &LogseqOptions,
+) -> Result<Vec<PathBuf>> {
+    let pages_dir = graph_dir.join("pages");
+    fs::create_dir_all(&pages_dir)?;
+
+    let mut used_names = HashSet::new();
+    let mut paths = Vec::new();
+
+    for book in books_clips {
+        println!("Exporting clips from {:?}", book.book_name);
+
+        let file_name = unique_filename(
+            render_filename(&options.filename_template, book, options.title_style),
+            &mut used_names,
+        );
+        let path = pages_dir.join(format!("{file_name}.md"));
+
+        fs::write(&path, book.to_logseq_page(options))?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+// Creating a Book's clippings Logseq page
+impl BookClips {
+    fn to_logseq_page(&self, options: &LogseqOptions) -> String {
+        let mut page = String::new();
+
+        // Page properties, the title one keeping the page name intact whatever the file name
+        page.push_str(&format!(
+            "title:: {}\n",
+            self.display_name(options.title_style)
+        ));
+        let author_links: Vec<String> = self
+            .authors
+            .iter()
+            .map(|author| format!("[[{}]]", author.name))
+            .collect();
+        // Books without author, like personal documents, have no author page to link to
+        if !author_links.is_empty() {
+            page.push_str(&format!("author:: {}\n", author_links.join(", ")));
+        }
+        if !options.tags.is_empty() {
+            page.push_str(&format!("tags:: {}\n", options.tags.join(", ")));
+        }
+        page.push('\n');
+
+        // Adding clips, with their note as a child block
+        for (clip, note) in self.annotated_highlights() {
+            page.push_str(&logseq_block(clip, 0, options));
+
+            if let Some(note) = note {
+                page.push_str(&logseq_block(note, 1, options));
+            }
+        }
+
+        page
+    }
+}
+
+/// Renders a clip as a Logseq block with its location and date properties
+/// # Variables
+/// * `clip` - The clip to render
+/// * `depth` - The nesting level of the block
+/// * `options` - The export options
+fn logseq_block(clip: &Clip, depth: usize, options: &LogseqOptions) -> String {
+    let indent = "\t".repeat(depth);
+    let mut block = String::new();
+
+    // Continuation lines and properties are aligned with the bullet content
+    for (idx, line) in clip.content.lines().enumerate() {
+        if idx == 0 {
+            block.push_str(&format!("{indent}- {line}\n"));
+        } else {
+            block.push_str(&format!("{indent}  {line}\n"));
+        }
+    }
+
+    // Empty clips still need a bullet for their properties
+    if block.is_empty() {
+        block.push_str(&format!("{indent}-\n"));
+    }
+
+    block.push_str(&format!(
+        "{indent}  location:: {}\n",
+        format_location(clip.location)
+    ));
+    block.push_str(&format!(
+        "{indent}  date:: [[{}]]\n",
+        clip.date.format(&options.journal_date_format)
+    ));
+
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+
+    #[test]
+    fn test_book_to_logseq_page() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+
+        let pages: Vec<String> = books_clips
+            .iter()
+            .map(|book| book.to_logseq_page(&LogseqOptions::default()))
+            .collect();
+        insta::assert_snapshot!(pages.join("\n"));
+    }
+}
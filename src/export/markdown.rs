@@ -0,0 +1,284 @@
+use crate::clippings::{BookClips, BookMetadata, Clip, ClipKind};
+use crate::export::{
+    copy_cover, format_location, render_filename, unique_filename, DEFAULT_FILENAME_TEMPLATE,
+};
+use crate::source::cover::BookCovers;
+use crate::title::TitleStyle;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Date format used in the front matter and next to each quote
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// Options for the Markdown export
+#[derive(Debug, Clone)]
+pub struct MarkdownOptions {
+    /// Template used for file names, see `export::render_filename`
+    pub filename_template: String,
+    /// Covers of the books, copied in a `covers` folder next to the Markdown files
+    pub covers: BookCovers,
+    /// How book names are written in file names and headings
+    pub title_style: TitleStyle,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            covers: BookCovers::default(),
+            title_style: TitleStyle::Full,
+        }
+    }
+}
+
+/// Writes one Markdown file per book in the output directory
+///
+/// # Arguments
+///
+/// * `output_dir` - The directory where the Markdown files will be written, created if needed
+/// * `books_clips` - The list of book clips to export
+/// * `options` - The export options
+///
+/// # Returns
+/// The paths of the written files, in the same order as `books_clips`
+///
+/// # Errors
+/// Raise on file system errors
+pub fn export_clips(
+    output_dir: &Path,
+    books_clips: &[BookClips],
+    options: &MarkdownOptions,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(output_dir)?;
+
+    let mut used_names = HashSet::new();
+    let mut paths = Vec::new();
+
+    for book in books_clips {
+        println!("Exporting clips from {:?}", book.book_name);
+
+        let file_name = unique_filename(
+            render_filename(&options.filename_template, book, options.title_style),
+            &mut used_names,
+        );
+        let path = output_dir.join(format!("{file_name}.md"));
+
+        let cover = match options.covers.cover_of(book) {
+            Some(cover) => Some(copy_cover(cover, output_dir, &file_name)?),
+            None => None,
+        };
+
+        fs::write(
+            &path,
+            book.to_markdown(cover.as_deref(), options.title_style),
+        )?;
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+// Creating a Book's clippings Markdown document
+impl BookClips {
+    /// # Arguments
+    ///
+    /// * `cover` - The path of the book cover, relative to the document
+    /// * `title_style` - How the book name is written
+    fn to_markdown(&self, cover: Option<&str>, title_style: TitleStyle) -> String {
+        let mut document = String::new();
+        let title = self.display_name(title_style);
+
+        // YAML front matter, JSON strings being valid YAML strings
+        document.push_str("---\n");
+        document.push_str(&format!("title: {}\n", yaml_string(&title)));
+        document.push_str(&format!("author: {}\n", yaml_string(&self.author())));
+        if let Some(metadata) = &self.metadata {
+            document.push_str(&metadata_front_matter(metadata));
+        }
+        let highlights: Vec<&Clip> = self
+            .clips
+            .iter()
+            .filter(|clip| clip.kind == ClipKind::Highlight && !clip.truncated)
+            .collect();
+        document.push_str(&format!("highlights: {}\n", highlights.len()));
+
+        if let (Some(first), Some(last)) = (
+            highlights.iter().map(|clip| clip.date).min(),
+            highlights.iter().map(|clip| clip.date).max(),
+        ) {
+            document.push_str(&format!("first_highlight: {}\n", first.to_rfc3339()));
+            document.push_str(&format!("last_highlight: {}\n", last.to_rfc3339()));
+        }
+
+        if let Some(cover) = cover {
+            document.push_str(&format!("cover: {}\n", yaml_string(cover)));
+        }
+
+        document.push_str("---\n\n");
+        document.push_str(&format!("# {title}\n\n"));
+        // Angle brackets allow spaces in the path
+        if let Some(cover) = cover {
+            document.push_str(&format!("![Cover](<{cover}>)\n\n"));
+        }
+        document.push_str(&format!("*{}*\n", self.author()));
+
+        // Adding clips
+        for (clip, note) in self.annotated_highlights() {
+            document.push('\n');
+            document.push_str(&quote_block(clip, note));
+        }
+
+        document
+    }
+}
+
+/// Renders a clip as a Markdown blockquote, finished with its note, location and date
+pub(crate) fn quote_block(clip: &Clip, note: Option<&Clip>) -> String {
+    let mut block = String::new();
+
+    // Lone notes are labelled as such, as they are the user's words and not the book's
+    if clip.kind == ClipKind::Note {
+        block.push_str("> **Note:**\n");
+    }
+
+    for line in clip.content.lines() {
+        block.push_str(&format!("> {line}\n"));
+    }
+
+    if let Some(note) = note {
+        block.push_str(">\n");
+        for (idx, line) in note.content.lines().enumerate() {
+            if idx == 0 {
+                block.push_str(&format!("> **Note:** {line}\n"));
+            } else {
+                block.push_str(&format!("> {line}\n"));
+            }
+        }
+    }
+
+    block.push_str(">\n");
+    block.push_str(&format!(
+        "> — Location {}, {}\n",
+        format_location(clip.location),
+        clip.date.format(DATE_FORMAT)
+    ));
+
+    block
+}
+
+/// Renders the library metadata of a book as YAML front matter fields, skipping unknown ones
+fn metadata_front_matter(metadata: &BookMetadata) -> String {
+    let mut fields = String::new();
+
+    if let Some(series) = &metadata.series {
+        fields.push_str(&format!("series: {}\n", yaml_string(series)));
+    }
+    if let Some(series_index) = metadata.series_index {
+        fields.push_str(&format!("series_index: {series_index}\n"));
+    }
+    if !metadata.tags.is_empty() {
+        let tags: Vec<String> = metadata.tags.iter().map(|tag| yaml_string(tag)).collect();
+        fields.push_str(&format!("tags: [{}]\n", tags.join(", ")));
+    }
+    if let Some(publisher) = &metadata.publisher {
+        fields.push_str(&format!("publisher: {}\n", yaml_string(publisher)));
+    }
+    if let Some(isbn) = &metadata.isbn {
+        fields.push_str(&format!("isbn: {}\n", yaml_string(isbn)));
+    }
+    if let Some(language) = &metadata.language {
+        fields.push_str(&format!("language: {}\n", yaml_string(language)));
+    }
+    if let Some(published) = metadata.published {
+        fields.push_str(&format!("published: {published}\n"));
+    }
+
+    fields
+}
+
+/// Quotes a string for YAML
+pub(crate) fn yaml_string(input: &str) -> String {
+    serde_json::to_string(input).unwrap_or_else(|_| unreachable!("A string is always serializable"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use crate::source::cover::tests::get_test_covers;
+    use crate::source::tests::local_text_dates;
+    use std::fs;
+
+    #[test]
+    fn test_book_to_markdown() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+
+        let documents: Vec<String> = books_clips
+            .iter()
+            .map(|book| book.to_markdown(None, TitleStyle::Full))
+            .collect();
+        insta::assert_snapshot!(local_text_dates(&documents.join("\n")));
+    }
+
+    #[test]
+    fn test_export_clips_with_cover() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+        let (covers, covers_dir) = get_test_covers();
+
+        let output_dir = covers_dir.join("export");
+        let options = MarkdownOptions {
+            covers,
+            ..Default::default()
+        };
+        let paths = export_clips(&output_dir, &books_clips, &options).expect("Could not export");
+
+        let shoe_dog = fs::read_to_string(paths.last().unwrap()).unwrap();
+        let cover_copied = output_dir.join("covers/Shoe Dog.jpg").exists();
+        fs::remove_dir_all(&covers_dir).ok();
+
+        assert!(cover_copied);
+        assert!(shoe_dog.contains("cover: \"covers/Shoe Dog.jpg\"\n"));
+        assert!(shoe_dog.contains("# Shoe Dog\n\n![Cover](<covers/Shoe Dog.jpg>)\n"));
+    }
+
+    #[test]
+    fn test_book_to_markdown_with_metadata() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let mut books_clips = parse_clips(input.as_str());
+        let shoe_dog = books_clips.last_mut().unwrap();
+        shoe_dog.metadata = Some(BookMetadata {
+            series: Some("Memoirs".to_string()),
+            series_index: Some(1.5),
+            tags: vec!["Biography".to_string(), "Business".to_string()],
+            publisher: Some("Simon & Schuster".to_string()),
+            isbn: Some("9781501135910".to_string()),
+            language: Some("eng".to_string()),
+            published: chrono::NaiveDate::from_ymd_opt(2016, 4, 26),
+            cover: None,
+        });
+
+        let document = shoe_dog.to_markdown(None, TitleStyle::Full);
+        let front_matter = document.split("---\n").nth(1).unwrap();
+        insta::assert_snapshot!(local_text_dates(front_matter));
+    }
+
+    #[test]
+    fn test_book_to_markdown_skips_truncated_clips() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let mut books_clips = parse_clips(input.as_str());
+        let second_brain = &mut books_clips[2];
+        let last = second_brain.clips.last_mut().unwrap();
+        last.truncated = true;
+        last.content = String::new();
+
+        // The truncated clip is neither shown, counted nor dated
+        let document = local_text_dates(&second_brain.to_markdown(None, TitleStyle::Full));
+        assert!(document.contains("highlights: 1\n"));
+        assert!(document.contains("last_highlight: 2022-12-18T10:20:38\n"));
+        assert_eq!(document.matches("> — Location").count(), 1);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::clippings::BookClips;
+use crate::export::markdown::{quote_block, yaml_string};
+use crate::export::{
+    render_filename, sanitize_filename, unique_filename, DEFAULT_FILENAME_TEMPLATE,
+};
+use crate::title::TitleStyle;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Characters that break Obsidian wikilinks
+const FORBIDDEN_LINK_CHARACTERS: [char; 4] = ['#', '^', '[', ']'];
+
+/// Options for the Obsidian export
+#[derive(Debug, Clone)]
+pub struct ObsidianOptions {
+    /// Template used for book notes file names, see `export::render_filename`
+    pub filename_template: String,
+    /// Folder of the vault where author notes are created, `None` to only link authors
+    pub authors_folder: Option<PathBuf>,
+    /// How book names are written in file names and headings
+    pub title_style: TitleStyle,
+}
+
+impl Default for ObsidianOptions {
+    fn default() -> Self {
+        Self {
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            authors_folder: Some(PathBuf::from("Authors")),
+            title_style: TitleStyle::Full,
+        }
+    }
+}
+
+/// Writes or updates one note per book in an Obsidian vault
+///
+/// Each clip gets a `^clip-id` block reference so it can be linked to from other notes.
+/// If a book note already exists, only the clips it does not reference yet are appended at the
+/// end of it, leaving the rest of the note untouched.
+///
+/// # Arguments
+///
+/// * `vault_dir` - The directory where the book notes will be written, created if needed
+/// * `books_clips` - The list of book clips to export
+/// * `options` - The export options
+///
+/// # Returns
+/// The paths of the book notes, in the same order as `books_clips`
+///
+/// # Errors
+/// Raise on file system errors
+pub fn export_clips(
+    vault_dir: &Path,
+    books_clips: &[BookClips],
+    options: &ObsidianOptions,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(vault_dir)?;
+
+    let mut used_names = HashSet::new();
+    let mut paths = Vec::new();
+
+    for book in books_clips {
+        let file_name = unique_filename(
+            render_filename(&options.filename_template, book, options.title_style),
+            &mut used_names,
+        );
+        let path = vault_dir.join(format!("{file_name}.md"));
+
+        let note = if path.exists() {
+            fs::read_to_string(&path)?
+        } else {
+            book.obsidian_header(options.title_style)
+        };
+
+        let (note, new_clips) = book.append_new_clips(note);
+        println!("Exporting {new_clips} new clips from {:?}", book.book_name);
+
+        fs::write(&path, note)?;
+        paths.push(path);
+
+        // Author notes are only created, never updated, as they belong to the user
+        if let Some(authors_folder) = &options.authors_folder {
+            let authors_dir = vault_dir.join(authors_folder);
+            for author in &book.authors {
+                let author_path =
+                    authors_dir.join(format!("{}.md", author_link_target(&author.name)));
+
+                if !author_path.exists() {
+                    fs::create_dir_all(&authors_dir)?;
+                    fs::write(&author_path, "---\ntags:\n  - author\n---\n")?;
+                }
+            }
+        }
+    }
+
+    Ok(paths)
+}
+
+// Creating a Book's clippings Obsidian note
+impl BookClips {
+    fn obsidian_header(&self, title_style: TitleStyle) -> String {
+        let title = self.display_name(title_style);
+        let author_links: Vec<String> = self
+            .authors
+            .iter()
+            .map(|author| format!("[[{}]]", author_link_target(&author.name)))
+            .collect();
+        // A single author stays a text property, several become a list
+        let author_property = match author_links.as_slice() {
+            [author_link] => yaml_string(author_link),
+            author_links => format!(
+                "[{}]",
+                author_links
+                    .iter()
+                    .map(|author_link| yaml_string(author_link))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+
+        let mut note = String::new();
+
+        note.push_str("---\n");
+        note.push_str(&format!("title: {}\n", yaml_string(&title)));
+        note.push_str(&format!("author: {author_property}\n"));
+        note.push_str("tags:\n  - kindle\n");
+        note.push_str("---\n\n");
+        note.push_str(&format!("# {title}\n\n"));
+        if !author_links.is_empty() {
+            note.push_str(&format!("by {}\n", author_links.join(" & ")));
+        }
+
+        note
+    }
+
+    /// Appends the clips whose block reference is not in the note yet
+    /// # Returns
+    /// * `(String, usize)` - The updated note and the number of appended clips
+    fn append_new_clips(&self, mut note: String) -> (String, usize) {
+        let existing_ids: HashSet<&str> = note
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix('^'))
+            .collect();
+
+        let new_clips: Vec<_> = self
+            .annotated_highlights()
+            .into_iter()
+            .filter(|(clip, _)| !existing_ids.contains(clip.id().as_str()))
+            .collect();
+
+        if new_clips.is_empty() {
+            return (note, 0);
+        }
+
+        // Block references on quotes have to be on their own line, surrounded by blank lines
+        let mut appended = String::new();
+        for (clip, note) in &new_clips {
+            appended.push('\n');
+            appended.push_str(&quote_block(clip, *note));
+            appended.push_str(&format!("\n^{}\n", clip.id()));
+        }
+
+        if !note.ends_with('\n') {
+            note.push('\n');
+        }
+        note.push_str(&appended);
+
+        (note, new_clips.len())
+    }
+}
+
+/// Returns the note name an author is linked to
+fn author_link_target(author: &str) -> String {
+    sanitize_filename(author)
+        .chars()
+        .filter(|c| !FORBIDDEN_LINK_CHARACTERS.contains(c))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clippings::{parse_clips, BookClips};
+    use crate::title::TitleStyle;
+    use std::fs;
+
+    #[test]
+    fn test_append_new_clips() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+        let book = &books_clips[2];
+
+        // Simulating a note exported with only the first clip, then edited by the user
+        let first_export = BookClips {
+            book_name: book.book_name.clone(),
+            authors: book.authors.clone(),
+            clips: vec![book.clips[0].clone()],
+            metadata: None,
+        };
+        let (note, appended) =
+            first_export.append_new_clips(book.obsidian_header(TitleStyle::Full));
+        assert_eq!(appended, 1);
+
+        let edited_note = format!(
+            "{}\nMy own thoughts about this\n",
+            note.replace("# Building", "# My title for Building")
+        );
+
+        let (updated_note, appended) = book.append_new_clips(edited_note.clone());
+        assert_eq!(appended, 1);
+        assert!(updated_note.starts_with(&edited_note));
+
+        // Exporting again does not change anything
+        let (_, appended) = book.append_new_clips(updated_note.clone());
+        assert_eq!(appended, 0);
+
+        insta::assert_snapshot!(updated_note);
+    }
+}
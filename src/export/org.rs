@@ -0,0 +1,132 @@
+use crate::clippings::{BookClips, Clip, ClipKind};
+use crate::export::format_location;
+use crate::title::TitleStyle;
+use anyhow::Result;
+use std::io::Write;
+
+/// Format of Org inactive timestamps
+const TIMESTAMP_FORMAT: &str = "[%Y-%m-%d %a %H:%M]";
+
+/// Writes the book clips as a single Org document, one heading per book
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the document, for example a file or stdout
+/// * `books_clips` - The list of book clips to export
+/// * `title_style` - How the book headings are written, see `BookTitle::format`
+///
+/// # Errors
+/// Raise on IO errors
+pub fn write_org<W: Write>(
+    mut writer: W,
+    books_clips: &[BookClips],
+    title_style: TitleStyle,
+) -> Result<()> {
+    writeln!(writer, "#+TITLE: Kindle highlights")?;
+
+    for book in books_clips {
+        write!(writer, "\n{}", book.to_org_heading(title_style))?;
+    }
+
+    Ok(())
+}
+
+// Creating a Book's clippings Org heading
+impl BookClips {
+    fn to_org_heading(&self, title_style: TitleStyle) -> String {
+        let highlights = self.annotated_highlights();
+
+        let mut heading = format!("* {}\n", self.display_name(title_style));
+        heading.push_str(":PROPERTIES:\n");
+        heading.push_str(&format!(":AUTHOR: {}\n", self.author()));
+        heading.push_str(&format!(
+            ":HIGHLIGHTS: {}\n",
+            highlights
+                .iter()
+                .filter(|(clip, _)| clip.kind == ClipKind::Highlight)
+                .count()
+        ));
+        heading.push_str(":END:\n");
+
+        for (clip, note) in highlights {
+            heading.push_str(&org_subheading(clip, note));
+        }
+
+        heading
+    }
+}
+
+/// Renders a clip as an Org subheading with its quote and note
+fn org_subheading(clip: &Clip, note: Option<&Clip>) -> String {
+    let label = if clip.kind == ClipKind::Note {
+        "Note"
+    } else {
+        "Location"
+    };
+
+    let mut subheading = format!(
+        "** {} {label} {}\n",
+        clip.date.format(TIMESTAMP_FORMAT),
+        format_location(clip.location)
+    );
+    subheading.push_str(":PROPERTIES:\n");
+    subheading.push_str(&format!(":CUSTOM_ID: {}\n", clip.id()));
+    subheading.push_str(&format!(":LOCATION: {}\n", format_location(clip.location)));
+    subheading.push_str(":END:\n");
+
+    // Lone notes are the user's words so they are not quoted
+    if clip.kind == ClipKind::Note {
+        subheading.push_str(&org_paragraph(&clip.content));
+        return subheading;
+    }
+
+    subheading.push_str("#+begin_quote\n");
+    subheading.push_str(&org_paragraph(&clip.content));
+    subheading.push_str("#+end_quote\n");
+
+    if let Some(note) = note {
+        subheading.push_str(&org_paragraph(&note.content));
+    }
+
+    subheading
+}
+
+/// Escapes the lines Org would parse as headings or keywords, with a leading comma
+fn org_paragraph(content: &str) -> String {
+    content
+        .lines()
+        .map(|line| {
+            if line.starts_with('*') || line.starts_with("#+") {
+                format!(",{line}\n")
+            } else {
+                format!("{line}\n")
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use std::fs;
+
+    #[test]
+    fn test_org_paragraph() {
+        assert_eq!(
+            org_paragraph("* Not a heading\n#+begin_src\nText"),
+            ",* Not a heading\n,#+begin_src\nText\n"
+        );
+    }
+
+    #[test]
+    fn test_write_org() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+
+        let mut output = Vec::new();
+        write_org(&mut output, &books_clips, TitleStyle::Full).expect("Could not write Org");
+
+        insta::assert_snapshot!(String::from_utf8(output).unwrap());
+    }
+}
@@ -1,3 +1,6 @@
-#![feature(slice_group_by)]
 pub mod clippings;
+pub mod export;
 pub mod notion;
+pub mod sink;
+pub mod source;
+pub mod title;
@@ -1,13 +1,90 @@
 use std::fs::OpenOptions;
-use std::path::PathBuf;
-use std::{env, fs};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
 
-use kindle_to_notion::{clippings, notion};
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use kindle_to_notion::clippings::{parse_clips, BookClips};
+use kindle_to_notion::export;
+use kindle_to_notion::export::csv::CsvLayout;
+use kindle_to_notion::notion::NotionSink;
+use kindle_to_notion::sink::{Sink, SyncState};
+use kindle_to_notion::source::apple_books::AppleBooksDirectory;
+use kindle_to_notion::source::asin::AsinResolver;
+use kindle_to_notion::source::calibre::CalibreLibrary;
+use kindle_to_notion::source::cover::BookCovers;
+use kindle_to_notion::source::kindle::KindleClippings;
+use kindle_to_notion::source::kobo::KoboDatabase;
+use kindle_to_notion::source::koreader::KoreaderDirectory;
+use kindle_to_notion::source::notebook::KindleNotebook;
+use kindle_to_notion::source::sidecar::{compare_with_clippings, KindleSidecars};
+use kindle_to_notion::source::toc::BookFiles;
+use kindle_to_notion::source::vocabulary::KindleVocabulary;
+use kindle_to_notion::source::{self, ClippingSource};
+use kindle_to_notion::title::TitleStyle;
 use std::io::prelude::*;
 
+#[derive(Parser)]
+#[command(version, about = "Parses Kindle clips and uploads them to Notion")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// How book names are displayed: `full`, `title` or `title-subtitle`, defaulting to the
+    /// `TITLE_STYLE` env variable, then to `title` for Notion and `full` for exports
+    #[arg(long, global = true)]
+    title_style: Option<TitleStyle>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Uploads the new clips to Notion, which is the default
+    Upload,
+    /// Exports the new clips to local files instead of Notion
+    Export {
+        /// Format of the export
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+        /// Output directory, or output file for JSON, CSV, vocabulary and Org formats which default to stdout,
+        /// and for the Anki and EPUB formats which default to `kindle_highlights.<format>`
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Only exports the books whose name contains this text, ignoring case
+        #[arg(long)]
+        book: Option<String>,
+        /// File name of the books for the Markdown, Obsidian and Logseq formats, with the
+        /// `{title}`, `{subtitle}` and `{author}` placeholders, defaulting to `{title}`
+        #[arg(long)]
+        filename_template: Option<String>,
+    },
+    /// Compares the annotations of the Kindle sidecar files with the clippings file
+    CompareSidecars,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Markdown,
+    Obsidian,
+    Logseq,
+    Json,
+    Ndjson,
+    Csv,
+    ReadwiseCsv,
+    Anki,
+    Html,
+    Org,
+    Epub,
+    /// Kindle Vocabulary Builder lookups as CSV, read from `vocab.db`
+    VocabularyCsv,
+    /// Kindle Vocabulary Builder lookups as NDJSON, read from `vocab.db`
+    VocabularyNdjson,
+}
+
 fn main() {
+    let cli = Cli::parse();
+
     // For simplicity, the conf is read from a .env file at the moment
-    dotenvy::dotenv().expect(".env file not found");
+    // It is only mandatory for Notion, exports working with the default clippings location
+    let dotenv = dotenvy::dotenv();
 
     // Getting the clippings location
     let clippings_location: PathBuf = env::var("CLIPPINGS_LOCATION").map_or_else(
@@ -17,32 +94,418 @@ fn main() {
         PathBuf::from,
     );
 
-    // Reading the clippings
-    let clippings_text = fs::read_to_string(&clippings_location).expect("Clippings file not found");
+    // Reading the clips of every source, the clippings file being optional for the users of
+    // other readers
+    let mut sources: Vec<Box<dyn ClippingSource>> = Vec::new();
+    if env::var("CLIPPINGS_LOCATION").is_ok() || clippings_location.exists() {
+        sources.push(Box::new(KindleClippings::new(&clippings_location)));
+    }
+    if let Ok(kobo_location) = env::var("KOBO_DATABASE_LOCATION") {
+        sources.push(Box::new(KoboDatabase::new(Path::new(&kobo_location))));
+    }
+    if let Ok(koreader_location) = env::var("KOREADER_LOCATION") {
+        sources.push(Box::new(KoreaderDirectory::new(Path::new(
+            &koreader_location,
+        ))));
+    }
+    if let Ok(apple_books_location) = env::var("APPLE_BOOKS_LOCATION") {
+        sources.push(Box::new(AppleBooksDirectory::new(Path::new(
+            &apple_books_location,
+        ))));
+    }
+    // Sidecars lack the highlighted text, so they only enrich the clips of the other sources
+    if let Ok(sidecar_location) = env::var("KINDLE_SIDECAR_LOCATION") {
+        sources.push(Box::new(KindleSidecars::new(Path::new(&sidecar_location))));
+    }
+    // Notebooks name books like the store, so they only enrich the clips of the other sources
+    if let Ok(notebook_location) = env::var("KINDLE_NOTEBOOK_LOCATION") {
+        sources.push(Box::new(KindleNotebook::new(Path::new(&notebook_location))));
+    }
+    let mut books_clips = source::read_books(&sources);
+
+    // Adding the series, tags, publisher and cover of the books found in a Calibre library
+    let calibre_location = env::var("CALIBRE_LIBRARY_LOCATION").ok().map(PathBuf::from);
+    if let Some(calibre_location) = &calibre_location {
+        match CalibreLibrary::new(calibre_location).enrich(&mut books_clips) {
+            Ok(found) => eprintln!("Found {found} books in the Calibre library"),
+            Err(err) => eprintln!("Could not read the Calibre library: {err:#}"),
+        }
+    }
+    attribute_chapters(
+        &mut books_clips,
+        &clippings_location,
+        calibre_location.as_deref(),
+    );
+
+    // Using stderr as JSON exports can be written to stdout
+    eprintln!("Found {} books with new clips", books_clips.len());
+    report_truncated_clips(&books_clips);
+
+    let title_style = cli.title_style.or_else(|| {
+        env::var("TITLE_STYLE")
+            .ok()
+            .map(|style| style.parse().expect("Invalid TITLE_STYLE env variable"))
+    });
 
-    // Creating our clips data
-    let books_clips = clippings::parse_clips(clippings_text.as_str());
+    match cli.command {
+        None | Some(Command::Upload) => {
+            dotenv.expect(".env file not found");
+            upload(
+                &books_clips,
+                &clippings_location,
+                title_style.unwrap_or(TitleStyle::Title),
+            );
+        }
+        Some(Command::CompareSidecars) => {
+            compare_sidecars(&clippings_location).expect("Could not compare sidecars");
+        }
+        Some(Command::Export {
+            format,
+            output,
+            book,
+            filename_template,
+        }) => {
+            if let Some(book) = book {
+                books_clips.retain(|clips| {
+                    clips
+                        .book_name
+                        .to_lowercase()
+                        .contains(&book.to_lowercase())
+                });
+            }
 
-    println!("Found {} books with new clips", books_clips.len());
+            let covers = device_covers(&device_asins(&clippings_location));
+            let title_style = title_style.unwrap_or(TitleStyle::Full);
+            let filename_template =
+                filename_template.unwrap_or_else(|| export::DEFAULT_FILENAME_TEMPLATE.to_string());
+            export(
+                &books_clips,
+                format,
+                output,
+                covers,
+                title_style,
+                filename_template,
+            )
+            .expect("Failed to export clips");
+        }
+    }
+}
 
+/// Uploads the clips to Notion, then marks them as uploaded in the clippings file
+///
+/// The clippings file is only marked once every book got uploaded, the sync state keeping track
+/// of the clips already uploaded so they are not uploaded again on the next run.
+/// Clips past the clipping limit do not prevent the marking, they are reported by
+/// `report_truncated_clips` before the upload.
+fn upload(books_clips: &[BookClips], clippings_location: &Path, title_style: TitleStyle) {
     // Reading the environment variables for Notion
     let api_key = env::var("NOTION_API_KEY").expect("NOTION_API_KEY env variable not set");
-    let parent_page_id = env::var("NOTION_PAGE_ID").expect("NOTION_PAGE_ID env variable not set");
+    // Books are pages of a parent page, or rows of a database with their metadata as properties
+    let database_id = env::var("NOTION_DATABASE_ID").ok();
+    let parent_page_id = env::var("NOTION_PAGE_ID");
+    let state_location = env::var("SYNC_STATE_LOCATION")
+        .map_or_else(|_| PathBuf::from("sync_state.json"), PathBuf::from);
+
+    let mut state = SyncState::load(&state_location).expect("Could not read sync state");
+    let asins = device_asins(clippings_location);
+    let notion = match database_id {
+        Some(database_id) => NotionSink::in_database(&api_key, &database_id),
+        None => NotionSink::new(
+            &api_key,
+            &parent_page_id.expect("NOTION_PAGE_ID or NOTION_DATABASE_ID env variable not set"),
+        ),
+    };
+    let mut notion = notion
+        .with_covers(device_covers(&asins))
+        .with_asins(asins)
+        .with_title_style(title_style);
 
     // Uploading to Notion
-    notion::upload_clips(api_key.as_str(), parent_page_id.as_str(), &books_clips)
-        .expect("Failed to upload to Notion");
+    let results = notion.sync(books_clips, &mut state);
+
+    // Vocabulary lookups go to their own database, if one is configured
+    // Errors are only logged, so the sync state of the uploaded books still gets saved
+    if let Ok(database_id) = env::var("NOTION_VOCABULARY_DATABASE_ID") {
+        let uploaded = KindleVocabulary::new(&vocabulary_location())
+            .read_lookups()
+            .context("Could not read vocabulary")
+            .and_then(|lookups| notion.sync_vocabulary(&database_id, &lookups, &mut state));
+
+        match uploaded {
+            Ok(uploaded) => eprintln!("Uploaded {uploaded} vocabulary lookups"),
+            Err(err) => eprintln!("Could not upload vocabulary: {err:#}"),
+        }
+    }
 
-    // Marking the end of the clippings only if clips were found
-    if books_clips.is_empty() {
+    state
+        .save(&state_location)
+        .expect("Could not write sync state");
+
+    let mut failed = false;
+    for result in results {
+        if let Err(err) = result.outcome {
+            eprintln!("Could not upload {:?}: {err}", result.book_name);
+            failed = true;
+        }
+    }
+
+    // Marking the end of the clippings only if clips were found and all of them got uploaded,
+    // and if there is a clippings file to mark
+    if books_clips.is_empty() || failed || !clippings_location.exists() {
         return;
     }
 
     let mut file = OpenOptions::new()
-        .write(true)
         .append(true)
-        .open(&clippings_location)
+        .open(clippings_location)
         .expect("Could not open clippings file as appendable");
 
     writeln!(file, "#==========").expect("Could not write to clippings file");
 }
+
+/// Prints the number of clips past the clipping limit of each book, with their locations so
+/// their text can be recovered manually
+fn report_truncated_clips(books_clips: &[BookClips]) {
+    for book in books_clips {
+        let truncated = book.truncated_clips();
+        if truncated.is_empty() {
+            continue;
+        }
+
+        let locations: Vec<String> = truncated
+            .iter()
+            .map(|clip| export::format_location(clip.location))
+            .collect();
+        eprintln!(
+            "{:?}: {} clips past the clipping limit, at locations {}",
+            book.book_name,
+            truncated.len(),
+            locations.join(", ")
+        );
+    }
+}
+
+/// Prints the differences between the sidecar annotations and the clippings file
+fn compare_sidecars(clippings_location: &Path) -> anyhow::Result<()> {
+    let sidecar_location = env::var("KINDLE_SIDECAR_LOCATION")
+        .map_or_else(|_| PathBuf::from("documents"), PathBuf::from);
+    let sidecar_clips = KindleSidecars::new(&sidecar_location).read_clips()?;
+
+    // Every clipping is compared, including the ones before the upload marker
+    let input = fs::read_to_string(clippings_location)?
+        .lines()
+        .filter(|line| line.trim() != "#==========")
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    for comparison in compare_with_clippings(&sidecar_clips, &parse_clips(&input)) {
+        println!(
+            "{}: {} in both, {} only in the sidecars, {} only in the clippings file",
+            comparison.book_name,
+            comparison.matched,
+            comparison.only_in_sidecars.len(),
+            comparison.only_in_clippings.len()
+        );
+
+        for clip in &comparison.only_in_sidecars {
+            println!(
+                "  + {:?} at location {}-{}, missing from the clippings file",
+                clip.kind, clip.location.0, clip.location.1
+            );
+        }
+        for clip in &comparison.only_in_clippings {
+            println!(
+                "  - {:?} at location {}-{}, deleted or moved on the Kindle",
+                clip.kind, clip.location.0, clip.location.1
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the ASIN of the books whose file is next to the clippings file, in the Kindle
+/// `documents` directory
+fn device_asins(clippings_location: &Path) -> AsinResolver {
+    let Some(documents) = clippings_location
+        .parent()
+        .filter(|documents| !documents.as_os_str().is_empty())
+    else {
+        return AsinResolver::default();
+    };
+
+    AsinResolver::scan(documents).unwrap_or_else(|err| {
+        eprintln!("Could not resolve the book ASINs: {err:#}");
+        AsinResolver::default()
+    })
+}
+
+/// Sets the chapter of the clips from the table of contents of the book files, found next to the
+/// clippings file and in the Calibre library
+fn attribute_chapters(
+    books_clips: &mut [BookClips],
+    clippings_location: &Path,
+    calibre_location: Option<&Path>,
+) {
+    let documents = clippings_location
+        .parent()
+        .filter(|documents| !documents.as_os_str().is_empty());
+    let dirs: Vec<&Path> = documents
+        .into_iter()
+        .chain(calibre_location)
+        .filter(|dir| dir.is_dir())
+        .collect();
+    if dirs.is_empty() {
+        return;
+    }
+
+    match BookFiles::scan(&dirs) {
+        Ok(book_files) => {
+            let attributed = book_files.attribute_chapters(books_clips);
+            eprintln!("Found the chapter of {attributed} clips");
+        }
+        Err(err) => eprintln!("Could not read the book files: {err:#}"),
+    }
+}
+
+/// Returns the covers of the books, from the Kindle thumbnails
+fn device_covers(asins: &AsinResolver) -> BookCovers {
+    let thumbnails_location: PathBuf = env::var("THUMBNAILS_LOCATION")
+        .map_or_else(|_| ["system", "thumbnails"].iter().collect(), PathBuf::from);
+    if !thumbnails_location.is_dir() {
+        return BookCovers::default();
+    }
+
+    BookCovers::scan(&thumbnails_location, asins).unwrap_or_else(|err| {
+        eprintln!("Could not read the book covers: {err:#}");
+        BookCovers::default()
+    })
+}
+
+/// Returns the location of the Kindle Vocabulary Builder database
+fn vocabulary_location() -> PathBuf {
+    env::var("VOCABULARY_LOCATION").map_or_else(
+        |_| ["system", "vocabulary", "vocab.db"].iter().collect(),
+        PathBuf::from,
+    )
+}
+
+/// Exports the clips in the given format, with the book covers for the formats showing them and
+/// the file name template for the formats writing one file per book
+fn export(
+    books_clips: &[BookClips],
+    format: ExportFormat,
+    output: Option<PathBuf>,
+    covers: BookCovers,
+    title_style: TitleStyle,
+    filename_template: String,
+) -> anyhow::Result<()> {
+    // File based formats write in an `export` folder by default
+    let output_dir = output.clone().unwrap_or_else(|| PathBuf::from("export"));
+
+    match format {
+        ExportFormat::Markdown => {
+            let options = export::markdown::MarkdownOptions {
+                covers,
+                title_style,
+                filename_template,
+            };
+            export::markdown::export_clips(&output_dir, books_clips, &options)?;
+        }
+        ExportFormat::Obsidian => {
+            let options = export::obsidian::ObsidianOptions {
+                title_style,
+                filename_template,
+                ..Default::default()
+            };
+            export::obsidian::export_clips(&output_dir, books_clips, &options)?;
+        }
+        ExportFormat::Logseq => {
+            let options = export::logseq::LogseqOptions {
+                title_style,
+                filename_template,
+                ..Default::default()
+            };
+            export::logseq::export_clips(&output_dir, books_clips, &options)?;
+        }
+        ExportFormat::Json => {
+            write_output(output, |writer| {
+                export::json::write_json(writer, books_clips, title_style)
+            })?;
+        }
+        ExportFormat::Ndjson => {
+            write_output(output, |writer| {
+                export::json::write_ndjson(writer, books_clips, title_style)
+            })?;
+        }
+        ExportFormat::Csv => write_output(output, |writer| {
+            export::csv::write_csv(writer, books_clips, CsvLayout::Generic, title_style)
+        })?,
+        ExportFormat::ReadwiseCsv => write_output(output, |writer| {
+            export::csv::write_csv(writer, books_clips, CsvLayout::Readwise, title_style)
+        })?,
+        ExportFormat::Html => {
+            let options = export::html::HtmlOptions {
+                covers,
+                title_style,
+                ..Default::default()
+            };
+            export::html::export_clips(&output_dir, books_clips, &options)?;
+        }
+        ExportFormat::Org => {
+            write_output(output, |writer| {
+                export::org::write_org(writer, books_clips, title_style)
+            })?;
+        }
+        ExportFormat::VocabularyCsv => {
+            let lookups = KindleVocabulary::new(&vocabulary_location()).read_lookups()?;
+            write_output(output, |writer| {
+                export::csv::write_vocabulary_csv(writer, &lookups)
+            })?;
+        }
+        ExportFormat::VocabularyNdjson => {
+            let lookups = KindleVocabulary::new(&vocabulary_location()).read_lookups()?;
+            write_output(output, |writer| {
+                export::json::write_vocabulary_ndjson(writer, &lookups)
+            })?;
+        }
+        ExportFormat::Epub => {
+            let output_file = output.unwrap_or_else(|| PathBuf::from("kindle_highlights.epub"));
+            let options = export::epub::EpubOptions {
+                covers,
+                title_style,
+                ..Default::default()
+            };
+            export::epub::export_clips(&output_file, books_clips, &options)?;
+        }
+        ExportFormat::Anki => {
+            let output_file = output.unwrap_or_else(|| PathBuf::from("kindle_highlights.apkg"));
+            let options = export::anki::AnkiOptions {
+                title_style,
+                ..Default::default()
+            };
+            export::anki::export_clips(&output_file, books_clips, &options)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes to the output file, or to stdout if no output was given
+///
+/// The writer is flushed once written, as a buffered writer dropped without it loses its write
+/// errors.
+fn write_output(
+    output: Option<PathBuf>,
+    write: impl FnOnce(&mut dyn Write) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    write(&mut *writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
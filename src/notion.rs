@@ -1,14 +1,25 @@
-use crate::clippings::BookClips;
-use anyhow::Result;
-use chrono::{DateTime, Local};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use crate::clippings::{BookClips, ClipKind};
+use crate::sink::{BookResult, Sink, SyncState};
+use crate::source::asin::{kindle_link, AsinResolver};
+use crate::source::cover::BookCovers;
+use crate::source::vocabulary::VocabLookup;
+use crate::title::TitleStyle;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use reqwest::blocking::Response;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
 use reqwest::{self, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
 
 const NOTION_API_URL: &str = "https://api.notion.com/v1/pages";
+const NOTION_FILE_UPLOADS_URL: &str = "https://api.notion.com/v1/file_uploads";
 
 /// Uploads the book clips to Notion
 ///
+/// Every clip gets uploaded, see [`NotionSink`] to skip the clips uploaded by previous runs.
+///
 /// # Arguments
 ///
 /// * `api_key` - The Notion API key
@@ -16,72 +27,331 @@ const NOTION_API_URL: &str = "https://api.notion.com/v1/pages";
 /// * `books_clips` - The list of book clips to upload
 ///
 /// # Errors
-/// Raise on HTTP errors from the API call to Notion
-pub fn upload_clips(
-    api_key: &str,
-    parent_page_id: &str,
-    books_clips: &Vec<BookClips>,
-) -> Result<()> {
-    let client = reqwest::blocking::Client::new();
-
-    // Defining custom headers
+/// Raise on the first book that could not be uploaded
+pub fn upload_clips(api_key: &str, parent_page_id: &str, books_clips: &[BookClips]) -> Result<()> {
+    let mut sink = NotionSink::new(api_key, parent_page_id);
+
+    for result in sink.sync(books_clips, &mut SyncState::default()) {
+        result.outcome?;
+    }
+
+    Ok(())
+}
+
+/// Notion sink, creating one page per book inside a parent page, or one row per book inside a
+/// database
+pub struct NotionSink {
+    api_key: String,
+    parent: Parent,
+    client: reqwest::blocking::Client,
+    asins: AsinResolver,
+    covers: BookCovers,
+    title_style: TitleStyle,
+}
+
+impl NotionSink {
+    /// # Arguments
+    ///
+    /// * `api_key` - The Notion API key
+    /// * `parent_page_id` - The ID of the parent page where the clips pages will be created
+    pub fn new(api_key: &str, parent_page_id: &str) -> Self {
+        Self {
+            api_key: api_key.to_string(),
+            parent: Parent::page(parent_page_id),
+            client: reqwest::blocking::Client::new(),
+            asins: AsinResolver::default(),
+            covers: BookCovers::default(),
+            title_style: TitleStyle::Title,
+        }
+    }
+
+    /// Creates the pages as rows of a database, whose properties hold the author and the library
+    /// metadata of the books, see [`crate::source::calibre::CalibreLibrary`]
+    ///
+    /// The database needs the properties `Series`, `Publisher`, `ISBN` and `Language` as text,
+    /// `Series index` as number, `Author` and `Tags` as multi-select and `Published` as date.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - The Notion API key
+    /// * `database_id` - The ID of the database where the clips pages will be created
+    pub fn in_database(api_key: &str, database_id: &str) -> Self {
+        Self {
+            parent: Parent::database(database_id),
+            ..Self::new(api_key, "")
+        }
+    }
+
+    /// Links the pages and quotes of the books whose ASIN is resolved to the Kindle apps
+    ///
+    /// # Arguments
+    ///
+    /// * `asins` - The resolver of the book ASINs, see [`AsinResolver::scan`]
+    pub fn with_asins(mut self, asins: AsinResolver) -> Self {
+        self.asins = asins;
+        self
+    }
+
+    /// Uses the Kindle thumbnails as page covers
+    ///
+    /// # Arguments
+    ///
+    /// * `covers` - The covers of the books, see [`BookCovers::scan`]
+    pub fn with_covers(mut self, covers: BookCovers) -> Self {
+        self.covers = covers;
+        self
+    }
+
+    /// Names the pages in the given style, their title only by default
+    ///
+    /// # Arguments
+    ///
+    /// * `title_style` - How the book names are displayed, see [`crate::title::BookTitle::format`]
+    pub fn with_title_style(mut self, title_style: TitleStyle) -> Self {
+        self.title_style = title_style;
+        self
+    }
+
+    /// Creates the page of a book
+    ///
+    /// # Errors
+    /// Raise on HTTP errors, or if Notion does not accept the page
+    fn create_page(&self, book: &BookClips) -> Result<()> {
+        let asin = self.asins.resolve(&book.book_name);
+        let mut query = book.create_page_query(self.parent.clone(), asin, self.title_style);
+
+        // A cover that cannot be uploaded does not prevent the page creation
+        if let Some(cover) = self.covers.cover_of(book) {
+            match self.upload_file(cover) {
+                Ok(file_upload_id) => query.cover = Some(Cover::file_upload(file_upload_id)),
+                Err(err) => eprintln!(
+                    "Could not upload the cover of {:?}: {err:#}",
+                    book.book_name
+                ),
+            }
+        }
+
+        self.post_page(&query)
+    }
+
+    /// Uploads a file, to be attached to a page
+    ///
+    /// # Returns
+    /// The ID of the file upload
+    ///
+    /// # Errors
+    /// Raise on file system or HTTP errors
+    fn upload_file(&self, path: &Path) -> Result<String> {
+        let filename = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let content_type = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("png") => "image/png",
+            _ => "image/jpeg",
+        };
+
+        let upload: FileUploadResponse = check_status(
+            self.client
+                .post(NOTION_FILE_UPLOADS_URL)
+                .bearer_auth(&self.api_key)
+                .headers(notion_headers())
+                .json(&FileUploadQuery {
+                    filename: filename.clone(),
+                    content_type: content_type.to_string(),
+                })
+                .send()?,
+        )?
+        .json()?;
+
+        // The multipart body only has the file, so it is written as is
+        let boundary = format!("kindle-to-notion-{}", upload.id);
+        let mut body = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: {content_type}\r\n\r\n",
+            filename.replace('"', "")
+        )
+        .into_bytes();
+        body.extend(fs::read(path)?);
+        body.extend(format!("\r\n--{boundary}--\r\n").into_bytes());
+
+        check_status(
+            self.client
+                .post(&upload.upload_url)
+                .bearer_auth(&self.api_key)
+                .headers(notion_headers())
+                .header(
+                    CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={boundary}"),
+                )
+                .body(body)
+                .send()?,
+        )?;
+
+        Ok(upload.id)
+    }
+
+    /// Uploads the vocabulary lookups not synced yet, as rows of a Notion database
+    ///
+    /// The database needs the `Word` title property, the `Stem`, `Usage`, `Book` and `Language`
+    /// text properties, and the `Date` date property.
+    ///
+    /// # Arguments
+    ///
+    /// * `database_id` - The ID of the vocabulary database
+    /// * `lookups` - The lookups to upload
+    /// * `state` - The sync state, updated with the uploaded lookups
+    ///
+    /// # Returns
+    /// The number of uploaded lookups
+    ///
+    /// # Errors
+    /// Raise on the first lookup that could not be uploaded, the previous ones staying synced
+    pub fn sync_vocabulary(
+        &self,
+        database_id: &str,
+        lookups: &[VocabLookup],
+        state: &mut SyncState,
+    ) -> Result<usize> {
+        const SINK_NAME: &str = "notion-vocabulary";
+
+        let mut uploaded = 0;
+        for lookup in lookups {
+            if state.is_id_synced(SINK_NAME, &lookup.id) {
+                continue;
+            }
+
+            println!("Uploading lookup of {:?}", lookup.word);
+            self.post_page(&VocabularyRowQuery::new(database_id, lookup))?;
+
+            state.mark_id_synced(SINK_NAME, &lookup.id);
+            uploaded += 1;
+        }
+
+        Ok(uploaded)
+    }
+
+    /// Creates a page, or a database row
+    ///
+    /// # Errors
+    /// Raise on HTTP errors, or if Notion does not accept the page
+    fn post_page<T: Serialize>(&self, query: &T) -> Result<()> {
+        let res = self
+            .client
+            .post(NOTION_API_URL)
+            .bearer_auth(&self.api_key)
+            .headers(notion_headers())
+            .json(query)
+            .send()?;
+
+        check_status(res)?;
+
+        Ok(())
+    }
+}
+
+/// Returns the headers of every Notion request
+fn notion_headers() -> HeaderMap {
     let mut headers = HeaderMap::new();
     headers.insert(
         HeaderName::from_static("notion-version"),
         HeaderValue::from_static("2022-06-28"),
     );
 
-    for book in books_clips {
-        println!("Uploading clips from {:?}", book.book_name);
+    headers
+}
 
-        let res = client
-            .post(NOTION_API_URL)
-            .bearer_auth(api_key)
-            .headers(headers.clone())
-            .json(&book.create_page_query(parent_page_id))
-            .send()?;
+/// Returns the response of a successful request
+///
+/// # Errors
+/// Raise if Notion did not accept the request
+fn check_status(res: Response) -> Result<Response> {
+    match res.status() {
+        StatusCode::OK => Ok(res),
+        StatusCode::BAD_REQUEST => bail!("Bad request: {:?}", res.text()?),
+        status => bail!("Unexpected status code: {:?}", status),
+    }
+}
 
-        match res.status() {
-            StatusCode::OK => {
+impl Sink for NotionSink {
+    fn name(&self) -> &str {
+        "notion"
+    }
+
+    fn sync(&mut self, books_clips: &[BookClips], state: &mut SyncState) -> Vec<BookResult> {
+        let mut results = Vec::new();
+
+        for book in books_clips {
+            let Some(new_clips) = state.new_clips(self.name(), book) else {
+                results.push(BookResult {
+                    book_name: book.book_name.clone(),
+                    outcome: Ok(0),
+                });
                 continue;
-            }
-            StatusCode::BAD_REQUEST => {
-                println!("Bad request: {:?}", res.text()?);
-            }
-            _ => {
-                println!("Unexpected status code: {:?}", res.status());
-            }
+            };
+
+            println!("Uploading clips from {:?}", book.book_name);
+
+            let outcome = self.create_page(&new_clips).map(|_| {
+                for clip in &new_clips.clips {
+                    state.mark_synced(self.name(), clip);
+                }
+                new_clips.clips.len()
+            });
+
+            results.push(BookResult {
+                book_name: book.book_name.clone(),
+                outcome,
+            });
         }
-    }
 
-    Ok(())
+        results
+    }
 }
 
 // Creating a Book's clippings JSON request to Notion
 impl BookClips {
-    fn create_page_query(&self, parent_page_id: &str) -> NotionPageQuery {
+    fn create_page_query(
+        &self,
+        parent: Parent,
+        asin: Option<&str>,
+        title_style: TitleStyle,
+    ) -> NotionPageQuery {
         let mut children = Vec::new();
 
-        // We split on : if it's in the name, as it's usually ridiculously long books names then
-        let page_name = if self.book_name.split(':').count() == 1 {
-            // If there's no : in the name, it's simply the book's name
-            &self.book_name
-        } else {
+        // Books names are usually ridiculously long, so the full name goes in a callout when
+        // the page title is shorter
+        let title = self.title();
+        let page_name = title.format(title_style);
+        if page_name != self.book_name {
             children.push(Child::new_callout(self.book_name.to_string(), "📕"));
-
-            // We return the first part of the string
-            self.book_name
-                .split(':')
-                .next()
-                .unwrap_or_else(|| unreachable!())
-        };
+        }
 
         // Adding the author
-        children.push(Child::new_callout(self.author.to_string(), "✍️"));
+        children.push(Child::new_callout(self.author(), "✍️"));
+        if let Some(asin) = asin {
+            let first_location = self.clips.first().map_or(0, |clip| clip.location.0);
+            children.push(
+                Child::new_callout("Open in Kindle".to_string(), "📱")
+                    .with_link(&kindle_link(asin, first_location)),
+            );
+        }
         children.push(Child::new_divider());
 
-        // Adding clips
-        for clip in &self.clips {
+        // Adding clips, with a heading when a new chapter starts
+        let mut chapter = None;
+        for (clip, note) in self.annotated_highlights() {
+            if clip.chapter.is_some() && clip.chapter != chapter {
+                chapter = clip.chapter.clone();
+                children.push(Child::new_heading(clip.chapter.clone().unwrap_or_default()));
+            }
+
+            // Lone notes are the user's words so they are not quoted
+            if clip.kind == ClipKind::Note {
+                children.push(Child::new_callout(clip.content.to_string(), "📝"));
+                continue;
+            }
+
             let mut split_content = Vec::new();
             let mut current_content = String::new();
 
@@ -103,51 +373,298 @@ impl BookClips {
 
             // We iterate on content blocks
             for (idx, content) in split_content.iter().enumerate() {
-                if idx < split_content.len() - 1 {
+                let mut quote = if idx < split_content.len() - 1 {
                     // First part of quote: no line jump or date
-                    children.push(Child::new_quote(content.to_string(), None));
+                    Child::new_quote(content.to_string(), None)
                 } else {
                     // Second part of quote: line jump and date
-                    children.push(Child::new_quote(content.to_string(), Some(clip.date)));
+                    Child::new_quote(content.to_string(), Some(clip.date))
                 };
+
+                // Keeping the highlight color as background
+                if let Some(quote) = &mut quote.quote {
+                    quote.color = clip.color.as_deref().and_then(Color::from_highlight);
+                }
+
+                // Clicking the quote opens the book at the highlight
+                if let Some(asin) = asin {
+                    quote = quote.with_link(&kindle_link(asin, clip.location.0));
+                }
+
+                children.push(quote);
+            }
+
+            // Adding the user's note below the quote
+            if let Some(note) = note {
+                children.push(Child::new_callout(note.content.to_string(), "📝"));
             }
         }
 
+        // Only database rows have properties besides the title
+        let book = parent.database_id.as_ref().map(|_| self.book_properties());
+
         NotionPageQuery {
-            parent: Parent {
-                page_id: parent_page_id.to_string(),
-            },
+            parent,
             icon: Icon {
                 emoji: "📖".to_string(),
             },
+            cover: None,
             properties: Properties {
                 title: vec![Title {
                     text: Text {
-                        content: page_name.to_string(),
+                        content: page_name,
+                        ..Default::default()
                     },
                 }],
+                book,
             },
             children,
         }
     }
+
+    /// Returns the database properties of the book, with its author and library metadata
+    fn book_properties(&self) -> BookProperties {
+        let metadata = self.metadata.clone().unwrap_or_default();
+        // Series markers of the book name, like `(The Lord of the Rings, Book 1)`, are the
+        // fallback of the library series
+        let title = self.title();
+        let series = metadata.series.or(title.series);
+        let series_index = metadata.series_index.or(title.series_index);
+
+        BookProperties {
+            author: MultiSelectProperty::new(self.authors.iter().map(|author| &author.name)),
+            series: series.as_deref().map(RichTextProperty::new),
+            series_index: series_index.map(|number| NumberProperty { number }),
+            tags: (!metadata.tags.is_empty()).then(|| MultiSelectProperty::new(&metadata.tags)),
+            publisher: metadata.publisher.as_deref().map(RichTextProperty::new),
+            isbn: metadata.isbn.as_deref().map(RichTextProperty::new),
+            language: metadata.language.as_deref().map(RichTextProperty::new),
+            published: metadata.published.map(|start| DayProperty {
+                date: Day { start },
+            }),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct NotionPageQuery {
     parent: Parent,
     icon: Icon,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cover: Option<Cover>,
     properties: Properties,
     children: Vec<Child>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Serialize, Deserialize)]
+struct Cover {
+    #[serde(rename = "type")]
+    type_field: String,
+    file_upload: FileUploadId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileUploadId {
+    id: String,
+}
+
+impl Cover {
+    fn file_upload(id: String) -> Self {
+        Self {
+            type_field: "file_upload".to_string(),
+            file_upload: FileUploadId { id },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FileUploadQuery {
+    filename: String,
+    content_type: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FileUploadResponse {
+    id: String,
+    upload_url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VocabularyRowQuery {
+    parent: Parent,
+    properties: VocabularyProperties,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct VocabularyProperties {
+    word: TitleProperty,
+    stem: RichTextProperty,
+    usage: RichTextProperty,
+    book: RichTextProperty,
+    language: RichTextProperty,
+    date: DateProperty,
+}
+
+#[derive(Debug, Serialize)]
+struct TitleProperty {
+    title: Vec<RichText>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RichTextProperty {
+    rich_text: Vec<RichText>,
+}
+
+impl RichTextProperty {
+    fn new(content: &str) -> Self {
+        Self {
+            rich_text: vec![RichText {
+                text: Some(Text {
+                    content: content.to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NumberProperty {
+    number: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MultiSelectProperty {
+    multi_select: Vec<SelectOption>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SelectOption {
+    name: String,
+}
+
+impl MultiSelectProperty {
+    fn new<T: AsRef<str>>(names: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            multi_select: names
+                .into_iter()
+                // Notion does not allow commas in options
+                .map(|name| SelectOption {
+                    name: name.as_ref().replace(',', ""),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DayProperty {
+    date: Day,
+}
+
+// Date without time
+#[derive(Debug, Serialize, Deserialize)]
+struct Day {
+    start: NaiveDate,
+}
+
+#[derive(Debug, Serialize)]
+struct DateProperty {
+    date: Date,
+}
+
+// Creating a lookup's row JSON request to Notion
+impl VocabularyRowQuery {
+    fn new(database_id: &str, lookup: &VocabLookup) -> Self {
+        let text = |content: &str| {
+            vec![RichText {
+                text: Some(Text {
+                    content: content.to_string(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }]
+        };
+
+        Self {
+            parent: Parent::database(database_id),
+            properties: VocabularyProperties {
+                word: TitleProperty {
+                    title: text(&lookup.word),
+                },
+                stem: RichTextProperty {
+                    rich_text: text(&lookup.stem),
+                },
+                usage: RichTextProperty {
+                    rich_text: text(&lookup.usage),
+                },
+                book: RichTextProperty {
+                    rich_text: text(&lookup.book),
+                },
+                language: RichTextProperty {
+                    rich_text: text(&lookup.language),
+                },
+                date: DateProperty {
+                    date: Date { start: lookup.date },
+                },
+            },
+        }
+    }
+}
+
+// Parent page or database
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 struct Parent {
-    page_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    database_id: Option<String>,
+}
+
+impl Parent {
+    fn page(page_id: &str) -> Self {
+        Self {
+            page_id: Some(page_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn database(database_id: &str) -> Self {
+        Self {
+            database_id: Some(database_id.to_string()),
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 struct Properties {
     title: Vec<Title>,
+    // Properties of the database rows
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    book: Option<BookProperties>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BookProperties {
+    #[serde(rename = "Author")]
+    author: MultiSelectProperty,
+    #[serde(rename = "Series", skip_serializing_if = "Option::is_none")]
+    series: Option<RichTextProperty>,
+    #[serde(rename = "Series index", skip_serializing_if = "Option::is_none")]
+    series_index: Option<NumberProperty>,
+    #[serde(rename = "Tags", skip_serializing_if = "Option::is_none")]
+    tags: Option<MultiSelectProperty>,
+    #[serde(rename = "Publisher", skip_serializing_if = "Option::is_none")]
+    publisher: Option<RichTextProperty>,
+    #[serde(rename = "ISBN", skip_serializing_if = "Option::is_none")]
+    isbn: Option<RichTextProperty>,
+    #[serde(rename = "Language", skip_serializing_if = "Option::is_none")]
+    language: Option<RichTextProperty>,
+    #[serde(rename = "Published", skip_serializing_if = "Option::is_none")]
+    published: Option<DayProperty>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -166,6 +683,8 @@ struct Child {
     pub divider: Option<Divider>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub quote: Option<Quote>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heading_3: Option<Heading>,
 }
 
 #[derive(Default, Debug, Serialize, Deserialize)]
@@ -182,6 +701,8 @@ enum BlockType {
     Divider,
     #[default]
     Quote,
+    #[serde(rename = "heading_3")]
+    Heading3,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -197,6 +718,13 @@ pub struct Callout {
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Quote {
     pub rich_text: Vec<RichText>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heading {
+    pub rich_text: Vec<RichText>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -204,6 +732,29 @@ pub struct Quote {
 pub enum Color {
     #[default]
     Default,
+    YellowBackground,
+    BlueBackground,
+    PinkBackground,
+    OrangeBackground,
+    PurpleBackground,
+    GreenBackground,
+    GrayBackground,
+}
+
+impl Color {
+    /// Returns the background color of a highlight color, like `yellow`
+    fn from_highlight(color: &str) -> Option<Self> {
+        match color.to_lowercase().as_str() {
+            "yellow" => Some(Self::YellowBackground),
+            "blue" => Some(Self::BlueBackground),
+            "pink" | "red" => Some(Self::PinkBackground),
+            "orange" => Some(Self::OrangeBackground),
+            "purple" => Some(Self::PurpleBackground),
+            "green" => Some(Self::GreenBackground),
+            "gray" | "grey" => Some(Self::GrayBackground),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -224,6 +775,13 @@ pub struct RichText {
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Text {
     pub content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<Link>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    pub url: String,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -258,7 +816,10 @@ impl Child {
             ..Default::default()
         };
         callout.rich_text.push(RichText {
-            text: Some(Text { content }),
+            text: Some(Text {
+                content,
+                ..Default::default()
+            }),
             ..Default::default()
         });
 
@@ -277,20 +838,63 @@ impl Child {
         child
     }
 
+    pub fn new_heading(content: String) -> Self {
+        Self {
+            type_field: BlockType::Heading3,
+            heading_3: Some(Heading {
+                rich_text: vec![RichText {
+                    text: Some(Text {
+                        content,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Links the texts of the block, the date mentions staying as they are
+    pub fn with_link(mut self, url: &str) -> Self {
+        let rich_texts = [
+            self.callout.as_mut().map(|callout| &mut callout.rich_text),
+            self.quote.as_mut().map(|quote| &mut quote.rich_text),
+        ];
+
+        for text in rich_texts
+            .into_iter()
+            .flatten()
+            .flat_map(|rich_text| rich_text.iter_mut())
+            .filter_map(|rich_text| rich_text.text.as_mut())
+            .filter(|text| !text.content.trim().is_empty())
+        {
+            text.link = Some(Link {
+                url: url.to_string(),
+            });
+        }
+
+        self
+    }
+
     pub fn new_quote(content: String, date: Option<DateTime<Local>>) -> Self {
         let mut child = Self::default();
 
         let mut quote = Quote {
             rich_text: vec![RichText {
-                text: Some(Text { content }),
+                text: Some(Text {
+                    content,
+                    ..Default::default()
+                }),
                 ..Default::default()
             }],
+            ..Default::default()
         };
 
         if let Some(date) = date {
             quote.rich_text.push(RichText {
                 text: Some(Text {
                     content: "\n".to_string(),
+                    ..Default::default()
                 }),
                 ..Default::default()
             });
@@ -308,3 +912,159 @@ impl Child {
         child
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::clippings::{parse_clips, BookMetadata};
+    use crate::title::TitleStyle;
+    use std::fs;
+
+    #[test]
+    fn test_create_page_query_with_chapters() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let mut books_clips = parse_clips(input.as_str());
+        let shoe_dog = books_clips.last_mut().unwrap();
+        shoe_dog.clips[0].chapter = Some("1965".to_string());
+        shoe_dog.clips[0].color = Some("yellow".to_string());
+
+        let query =
+            shoe_dog.create_page_query(super::Parent::page("parent"), None, TitleStyle::Title);
+        let children = serde_json::to_value(&query.children).unwrap();
+
+        // Author callout, divider, chapter heading, quote and note
+        assert_eq!(children[2]["type"], "heading_3");
+        assert_eq!(
+            children[2]["heading_3"]["rich_text"][0]["text"]["content"],
+            "1965"
+        );
+        assert_eq!(children[3]["quote"]["color"], "yellow_background");
+        assert_eq!(children[4]["type"], "callout");
+    }
+
+    #[test]
+    fn test_create_page_query_with_asin_and_cover() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+        let shoe_dog = books_clips.last().unwrap();
+
+        let mut query = shoe_dog.create_page_query(
+            super::Parent::page("parent"),
+            Some("B00ZYVIVA0"),
+            TitleStyle::Title,
+        );
+        assert!(serde_json::to_value(&query).unwrap().get("cover").is_none());
+
+        query.cover = Some(super::Cover::file_upload("upload".to_string()));
+        let page = serde_json::to_value(&query).unwrap();
+        assert_eq!(page["cover"]["type"], "file_upload");
+        assert_eq!(page["cover"]["file_upload"]["id"], "upload");
+
+        let children = &page["children"];
+
+        // Author callout, Kindle link callout, divider, quote and note
+        assert_eq!(
+            children[1]["callout"]["rich_text"][0]["text"]["link"]["url"],
+            "kindle://book?action=open&asin=B00ZYVIVA0&location=877"
+        );
+        assert_eq!(
+            children[3]["quote"]["rich_text"][0]["text"]["link"]["url"],
+            "kindle://book?action=open&asin=B00ZYVIVA0&location=877"
+        );
+        // The line break and date are not linked
+        assert!(children[3]["quote"]["rich_text"][1]["text"]
+            .get("link")
+            .is_none());
+        assert!(children[4]["callout"]["rich_text"][0]["text"]
+            .get("link")
+            .is_none());
+    }
+
+    #[test]
+    fn test_create_page_query_title() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let books_clips = parse_clips(input.as_str());
+        let second_brain = books_clips
+            .iter()
+            .find(|book| book.book_name.starts_with("Building a Second Brain"))
+            .unwrap();
+
+        // The qualifier `(2022)` is dropped and the full name goes in a callout
+        let query = second_brain.create_page_query(
+            super::Parent::page("parent"),
+            None,
+            TitleStyle::TitleSubtitle,
+        );
+        let page = serde_json::to_value(&query).unwrap();
+        assert_eq!(
+            page["properties"]["title"][0]["text"]["content"],
+            "Building a Second Brain: A Proven Method to Organize Your Digital Life and Unlock Your Creative Potential"
+        );
+        assert_eq!(
+            page["children"][0]["callout"]["rich_text"][0]["text"]["content"],
+            second_brain.book_name
+        );
+
+        // Books displayed with their full name have no callout
+        let query =
+            second_brain.create_page_query(super::Parent::page("parent"), None, TitleStyle::Full);
+        let page = serde_json::to_value(&query).unwrap();
+        assert_eq!(
+            page["properties"]["title"][0]["text"]["content"],
+            second_brain.book_name
+        );
+        assert_eq!(
+            page["children"][0]["callout"]["rich_text"][0]["text"]["content"],
+            "Tiago Forte"
+        );
+    }
+
+    #[test]
+    fn test_create_page_query_in_database() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let mut books_clips = parse_clips(input.as_str());
+        let shoe_dog = books_clips.last_mut().unwrap();
+
+        let query =
+            shoe_dog.create_page_query(super::Parent::page("parent"), None, TitleStyle::Title);
+        let page = serde_json::to_value(&query).unwrap();
+        assert_eq!(page["parent"], serde_json::json!({ "page_id": "parent" }));
+        assert!(page["properties"].get("Author").is_none());
+
+        shoe_dog.metadata = Some(BookMetadata {
+            series_index: Some(1.0),
+            tags: vec!["Biography".to_string(), "Sports, Running".to_string()],
+            published: chrono::NaiveDate::from_ymd_opt(2016, 4, 26),
+            ..Default::default()
+        });
+        let query = shoe_dog.create_page_query(
+            super::Parent::database("database"),
+            None,
+            TitleStyle::Title,
+        );
+        let page = serde_json::to_value(&query).unwrap();
+
+        assert_eq!(
+            page["parent"],
+            serde_json::json!({ "database_id": "database" })
+        );
+        insta::assert_yaml_snapshot!(page["properties"]);
+    }
+
+    #[test]
+    fn test_vocabulary_row_query() {
+        let lookups = crate::source::vocabulary::tests::get_test_lookups();
+
+        let query = super::VocabularyRowQuery::new("database", &lookups[0]);
+        let query = serde_json::to_value(&query).unwrap();
+
+        assert_eq!(query["parent"]["database_id"], "database");
+        assert_eq!(
+            query["properties"]["Word"]["title"][0]["text"]["content"],
+            "reflexively"
+        );
+        assert_eq!(
+            query["properties"]["Stem"]["rich_text"][0]["text"]["content"],
+            "reflexive"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use crate::clippings::{BookClips, Clip};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// A destination the book clips get synced to, like Notion
+///
+/// Implementations skip the clips the sync state marks as already synced, and mark the ones they
+/// sync, so running a sync again after a partial failure does not duplicate clips.
+pub trait Sink {
+    /// Name of the sink, which identifies its clips in the sync state
+    fn name(&self) -> &str;
+
+    /// Syncs the clips that are not synced yet
+    ///
+    /// # Arguments
+    ///
+    /// * `books_clips` - The list of book clips to sync
+    /// * `state` - The sync state, updated with the synced clips
+    ///
+    /// # Returns
+    /// The result of the sync of each book, in the same order as `books_clips`
+    fn sync(&mut self, books_clips: &[BookClips], state: &mut SyncState) -> Vec<BookResult>;
+}
+
+/// Result of the sync of a book
+#[derive(Debug)]
+pub struct BookResult {
+    pub book_name: String,
+    /// Number of synced clips, or the error that stopped the sync of the book
+    pub outcome: Result<usize>,
+}
+
+/// Clips already synced to each sink, persisted between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Identifiers of the synced clips, by sink name
+    synced: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl SyncState {
+    /// Reads the sync state from a JSON file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The sync state file, which may not exist yet
+    ///
+    /// # Errors
+    /// Raise if the file exists but cannot be read or parsed
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Writes the sync state to a JSON file
+    ///
+    /// # Errors
+    /// Raise on file system errors
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    /// Returns whether the clip was already synced to the sink
+    pub fn is_synced(&self, sink: &str, clip: &Clip) -> bool {
+        self.is_id_synced(sink, &clip.id())
+    }
+
+    /// Marks the clip as synced to the sink
+    pub fn mark_synced(&mut self, sink: &str, clip: &Clip) {
+        self.mark_id_synced(sink, &clip.id());
+    }
+
+    /// Returns whether the item with this identifier was already synced to the sink, for items
+    /// that are not clips like vocabulary lookups
+    pub fn is_id_synced(&self, sink: &str, id: &str) -> bool {
+        matches!(self.synced.get(sink), Some(ids) if ids.contains(id))
+    }
+
+    /// Marks the item with this identifier as synced to the sink
+    pub fn mark_id_synced(&mut self, sink: &str, id: &str) {
+        self.synced
+            .entry(sink.to_string())
+            .or_default()
+            .insert(id.to_string());
+    }
+
+    /// Returns the clips of the book that were not synced to the sink yet
+    ///
+    /// Truncated clips are left out, so they are synced once their text is recovered from
+    /// another source, see `Clip::truncated`.
+    ///
+    /// # Returns
+    /// * `Option<BookClips>` - The book with only its new clips, or `None` if all were synced
+    pub fn new_clips(&self, sink: &str, book: &BookClips) -> Option<BookClips> {
+        let clips: Vec<Clip> = book
+            .clips
+            .iter()
+            .filter(|clip| !clip.truncated && !self.is_synced(sink, clip))
+            .cloned()
+            .collect();
+
+        if clips.is_empty() {
+            return None;
+        }
+
+        Some(BookClips {
+            book_name: book.book_name.clone(),
+            authors: book.authors.clone(),
+            clips,
+            metadata: book.metadata.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+
+    fn get_test_books_clips() -> Vec<BookClips> {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        parse_clips(input.as_str())
+    }
+
+    #[test]
+    fn test_new_clips() {
+        let books_clips = get_test_books_clips();
+        let book = &books_clips[2];
+
+        let mut state = SyncState::default();
+        state.mark_synced("notion", &book.clips[0]);
+
+        // Only the second clip is new for Notion, while both are new for other sinks
+        let new_clips = state.new_clips("notion", book).unwrap();
+        assert_eq!(new_clips.clips.len(), 1);
+        assert_eq!(new_clips.clips[0].id(), book.clips[1].id());
+        assert_eq!(state.new_clips("markdown", book).unwrap().clips.len(), 2);
+
+        state.mark_synced("notion", &book.clips[1]);
+        assert!(state.new_clips("notion", book).is_none());
+    }
+
+    #[test]
+    fn test_new_clips_skips_truncated() {
+        let mut books_clips = get_test_books_clips();
+        let book = &mut books_clips[2];
+        book.clips[1].truncated = true;
+
+        // Truncated clips wait for their text to be recovered
+        let state = SyncState::default();
+        let new_clips = state.new_clips("notion", book).unwrap();
+        assert_eq!(new_clips.clips.len(), 1);
+        assert_eq!(new_clips.clips[0].id(), book.clips[0].id());
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let books_clips = get_test_books_clips();
+        let path = std::env::temp_dir().join(format!(
+            "kindle_to_notion-state-{}.json",
+            std::process::id()
+        ));
+
+        // A missing file is an empty state
+        let mut state = SyncState::load(&path).expect("Could not load missing state");
+        assert!(!state.is_synced("notion", &books_clips[0].clips[0]));
+
+        state.mark_synced("notion", &books_clips[0].clips[0]);
+        state.save(&path).expect("Could not save state");
+
+        let state = SyncState::load(&path).expect("Could not load state");
+        fs::remove_file(&path).ok();
+        assert!(state.is_synced("notion", &books_clips[0].clips[0]));
+    }
+}
@@ -0,0 +1,189 @@
+pub mod apple_books;
+pub mod asin;
+pub mod calibre;
+pub mod cover;
+pub mod kindle;
+pub mod kobo;
+pub mod koreader;
+pub mod notebook;
+pub mod sidecar;
+pub mod toc;
+pub mod vocabulary;
+
+use crate::clippings::{group_enriched_clips, BookClips, Clip};
+use anyhow::Result;
+
+/// A place clips are read from, like a Kindle clippings file
+///
+/// Sources only read clips, grouping them by book and removing duplicates is done by
+/// `read_books` so every source goes through the same pipeline.
+pub trait ClippingSource {
+    /// Name of the source, for logs
+    fn name(&self) -> &str;
+
+    /// Reads the clips of the source
+    ///
+    /// # Errors
+    /// Raise if the source cannot be read
+    fn read_clips(&self) -> Result<Vec<Clip>>;
+
+    /// Whether the clips of the source only enrich the clips of the other sources, the ones
+    /// matching none of them being dropped, see `group_enriched_clips`
+    fn enriches_only(&self) -> bool {
+        false
+    }
+}
+
+/// Reads the clips of every source and groups them by book
+///
+/// A source that cannot be read is logged and skipped, so a missing clippings file does not stop
+/// the other sources from being read
+///
+/// # Arguments
+///
+/// * `sources` - The sources to read, in order
+///
+/// # Returns
+/// The book clips of all the sources, a book read by several sources being a single `BookClips`,
+/// and the sources which only enrich the others adding to their clips
+pub fn read_books(sources: &[Box<dyn ClippingSource>]) -> Vec<BookClips> {
+    let mut clips = Vec::new();
+    let mut enrichments = Vec::new();
+
+    for source in sources {
+        let source_clips = match source.read_clips() {
+            Ok(source_clips) => source_clips,
+            Err(err) => {
+                eprintln!("Could not read clips from {}: {err:#}", source.name());
+                continue;
+            }
+        };
+        eprintln!("Read {} clips from {}", source_clips.len(), source.name());
+        if source.enriches_only() {
+            enrichments.extend(source_clips);
+        } else {
+            clips.extend(source_clips);
+        }
+    }
+
+    group_enriched_clips(clips, enrichments)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use chrono::{DateTime, FixedOffset, Utc};
+    use rusqlite::Connection;
+    use serde::Serialize;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// Creates an empty temporary directory for the fixtures of a test
+    ///
+    /// Named by process and thread, as tests run in parallel and several of them build the same
+    /// fixtures
+    pub(crate) fn create_fixture_directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "kindle_to_notion-{name}-{}-{:?}",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).expect("Could not create fixture directory");
+
+        dir
+    }
+
+    /// Builds a SQLite database from its SQL script
+    pub(crate) fn create_fixture_database(path: &Path, script: &Path) {
+        let sql = fs::read_to_string(script).expect("Fixture not found");
+        fs::remove_file(path).ok();
+
+        Connection::open(path)
+            .and_then(|connection| connection.execute_batch(&sql))
+            .expect("Could not fill fixture database");
+    }
+
+    /// Writes the dates of clips or lookups in UTC, so their snapshots do not depend on the
+    /// timezone of the machine, for the sources storing timestamps like Kobo
+    pub(crate) fn utc_dates<T: Serialize>(value: &T) -> Value {
+        map_dates(value, &|date| date.with_timezone(&Utc).to_rfc3339())
+    }
+
+    /// Writes the dates of clips or lookups without their offset, so their snapshots do not
+    /// depend on the timezone of the machine, for the sources storing local times like KOReader
+    pub(crate) fn local_dates<T: Serialize>(value: &T) -> Value {
+        map_dates(value, &|date| date.format("%Y-%m-%dT%H:%M:%S").to_string())
+    }
+
+    /// Writes the dates of a rendered export in UTC, so its snapshots do not depend on the
+    /// timezone of the machine, for the lookups read from timestamps
+    pub(crate) fn utc_text_dates(text: &str) -> String {
+        map_text_dates(text, &|date| date.with_timezone(&Utc).to_rfc3339())
+    }
+
+    /// Writes the dates of a rendered export without their offset, so its snapshots do not
+    /// depend on the timezone of the machine, for the clips parsed from local times
+    pub(crate) fn local_text_dates(text: &str) -> String {
+        map_text_dates(text, &|date| date.format("%Y-%m-%dT%H:%M:%S").to_string())
+    }
+
+    /// Replaces every RFC 3339 date of a rendered text, written without fractional seconds
+    fn map_text_dates(text: &str, format: &dyn Fn(DateTime<FixedOffset>) -> String) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(character) = rest.chars().next() {
+            // With a numeric offset like `+09:00`, or with `Z`
+            let date = [25, 20].into_iter().find_map(|length| {
+                let date = DateTime::parse_from_rfc3339(rest.get(..length)?).ok()?;
+                Some((length, date))
+            });
+
+            match date {
+                Some((length, date)) => {
+                    output.push_str(&format(date));
+                    rest = &rest[length..];
+                }
+                None => {
+                    output.push(character);
+                    rest = &rest[character.len_utf8()..];
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Replaces every `date` field of a serialized value
+    ///
+    /// The fields of the value are sorted by name, so its snapshots have a stable order
+    fn map_dates<T: Serialize>(
+        value: &T,
+        format: &dyn Fn(DateTime<FixedOffset>) -> String,
+    ) -> Value {
+        fn visit(value: &mut Value, format: &dyn Fn(DateTime<FixedOffset>) -> String) {
+            match value {
+                Value::Array(values) => values.iter_mut().for_each(|value| visit(value, format)),
+                Value::Object(fields) => {
+                    for (key, field) in fields.iter_mut() {
+                        match field {
+                            Value::String(date) if key == "date" => {
+                                let date = DateTime::parse_from_rfc3339(date)
+                                    .expect("Dates are serialized as RFC 3339");
+                                *field = Value::String(format(date));
+                            }
+                            _ => visit(field, format),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut value = serde_json::to_value(value).expect("Could not serialize value");
+        visit(&mut value, format);
+
+        value
+    }
+}
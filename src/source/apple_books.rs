@@ -0,0 +1,298 @@
+use crate::clippings::{parse_authors, Clip, ClipKind};
+use crate::source::ClippingSource;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Core Data dates are seconds since 2001-01-01 UTC, this is that date as a Unix timestamp
+const CORE_DATA_EPOCH: i64 = 978_307_200;
+
+/// Apple Books has no locations, so clips are located by EPUB spine position and offset,
+/// this many per spine item
+const LOCATIONS_PER_SPINE_ITEM: usize = 100_000;
+
+/// Directory containing copies of the Apple Books `AEAnnotation*.sqlite` and `BKLibrary*.sqlite`
+/// databases
+///
+/// On macOS they are in `~/Library/Containers/com.apple.iBooksX/Data/Documents/`, in the
+/// `AEAnnotation` and `BKLibrary` folders. Only the copies are read, so this works on any OS.
+pub struct AppleBooksDirectory {
+    path: PathBuf,
+}
+
+impl AppleBooksDirectory {
+    /// # Arguments
+    ///
+    /// * `path` - The directory containing the database copies
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Returns the databases of the directory whose file name starts with `prefix`
+    fn databases(&self, prefix: &str) -> Result<Vec<PathBuf>> {
+        let mut databases: Vec<PathBuf> = fs::read_dir(&self.path)
+            .with_context(|| format!("Could not read Apple Books directory {:?}", self.path))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()?;
+
+        databases.retain(|path| {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            file_name.starts_with(prefix) && file_name.ends_with(".sqlite")
+        });
+        databases.sort();
+
+        if databases.is_empty() {
+            bail!("No {prefix}*.sqlite database in {:?}", self.path);
+        }
+
+        Ok(databases)
+    }
+}
+
+/// Row of the `ZAEANNOTATION` table
+struct Annotation {
+    asset_id: String,
+    selected_text: Option<String>,
+    note: Option<String>,
+    creation_date: f64,
+    cfi: Option<String>,
+    range: (usize, usize),
+    style: Option<i64>,
+    is_underline: bool,
+    chapter: Option<String>,
+}
+
+impl ClippingSource for AppleBooksDirectory {
+    fn name(&self) -> &str {
+        "Apple Books"
+    }
+
+    fn read_clips(&self) -> Result<Vec<Clip>> {
+        // Title and author of the books, by asset ID
+        let mut books: HashMap<String, (String, String)> = HashMap::new();
+        for database in self.databases("BKLibrary")? {
+            let connection = open_read_only(&database)?;
+            let mut statement =
+                connection.prepare("SELECT ZASSETID, ZTITLE, ZAUTHOR FROM ZBKLIBRARYASSET")?;
+            let rows = statement.query_map([], |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })?;
+
+            for row in rows {
+                if let (Some(asset_id), Some(title), author) = row? {
+                    books.insert(asset_id, (title, author.unwrap_or_default()));
+                }
+            }
+        }
+
+        let mut clips = Vec::new();
+        for database in self.databases("AEAnnotation")? {
+            let connection = open_read_only(&database)?;
+
+            // Annotations without text nor note are reading positions
+            let mut statement = connection.prepare(
+                "SELECT ZANNOTATIONASSETID, ZANNOTATIONSELECTEDTEXT, ZANNOTATIONNOTE,
+                        ZANNOTATIONCREATIONDATE, ZANNOTATIONLOCATION, ZPLLOCATIONRANGESTART,
+                        ZPLLOCATIONRANGEEND, ZANNOTATIONSTYLE, ZANNOTATIONISUNDERLINE,
+                        ZFUTUREPROOFING5
+                 FROM ZAEANNOTATION
+                 WHERE ZANNOTATIONDELETED = 0
+                   AND (ZANNOTATIONSELECTEDTEXT IS NOT NULL OR ZANNOTATIONNOTE IS NOT NULL)
+                 ORDER BY ZANNOTATIONCREATIONDATE",
+            )?;
+            let annotations = statement
+                .query_map([], |row| {
+                    Ok(Annotation {
+                        asset_id: row.get(0)?,
+                        selected_text: row.get(1)?,
+                        note: row.get(2)?,
+                        creation_date: row.get(3)?,
+                        cfi: row.get(4)?,
+                        range: (
+                            row.get::<_, Option<usize>>(5)?.unwrap_or_default(),
+                            row.get::<_, Option<usize>>(6)?.unwrap_or_default(),
+                        ),
+                        style: row.get(7)?,
+                        is_underline: row.get::<_, Option<bool>>(8)?.unwrap_or_default(),
+                        chapter: row.get(9)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            for annotation in annotations {
+                let (book, author) = books
+                    .get(&annotation.asset_id)
+                    .cloned()
+                    .unwrap_or_else(|| (annotation.asset_id.clone(), String::new()));
+
+                clips.extend(annotation.to_clips(book, author));
+            }
+        }
+
+        Ok(clips)
+    }
+}
+
+impl Annotation {
+    /// Maps the annotation to its clips, a highlight with a note giving a highlight and a note
+    fn to_clips(&self, book: String, author: String) -> Vec<Clip> {
+        let spine_position = self.cfi.as_deref().and_then(cfi_spine_position);
+        let offset = spine_position.unwrap_or_default() * LOCATIONS_PER_SPINE_ITEM;
+
+        let base = Clip {
+            book,
+            authors: parse_authors(&author),
+            source_author: author,
+            kind: ClipKind::Highlight,
+            content: String::new(),
+            date: core_data_date(self.creation_date),
+            location: (
+                offset + self.range.0,
+                offset + self.range.1.max(self.range.0),
+            ),
+            page: None,
+            chapter: self.chapter.clone().filter(|chapter| !chapter.is_empty()),
+            progress: None,
+            color: annotation_style(self.style, self.is_underline).map(str::to_string),
+            truncated: false,
+            approximate_location: false,
+        };
+
+        let text = self.selected_text.as_deref().unwrap_or_default().trim();
+        let note = self.note.as_deref().unwrap_or_default().trim();
+
+        let mut clips = Vec::new();
+        if !text.is_empty() {
+            clips.push(Clip {
+                content: text.to_string(),
+                ..base.clone()
+            });
+        }
+
+        // Notes are located at the end of their highlight, like Kindle does
+        if !note.is_empty() {
+            clips.push(Clip {
+                kind: ClipKind::Note,
+                content: note.to_string(),
+                location: (base.location.1, base.location.1),
+                ..base
+            });
+        }
+
+        clips
+    }
+}
+
+/// Opens a database without ever writing to it
+fn open_read_only(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Could not open Apple Books database {path:?}"))
+}
+
+/// Returns the style of an annotation, which is its color or `underline`
+fn annotation_style(style: Option<i64>, is_underline: bool) -> Option<&'static str> {
+    if is_underline {
+        return Some("underline");
+    }
+
+    match style? {
+        1 => Some("green"),
+        2 => Some("blue"),
+        3 => Some("yellow"),
+        4 => Some("pink"),
+        5 => Some("purple"),
+        _ => None,
+    }
+}
+
+/// Returns the spine step of an EPUB CFI, which orders the chapters
+/// * Example: `epubcfi(/6/12[chapter2]!/4/2/6,/1:0,/1:120)` gives 12
+fn cfi_spine_position(cfi: &str) -> Option<usize> {
+    let path = cfi.strip_prefix("epubcfi(/6/")?;
+
+    path.split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|step| step.parse().ok())
+}
+
+/// Converts a Core Data timestamp to a local date
+fn core_data_date(timestamp: f64) -> DateTime<Local> {
+    let seconds = timestamp.trunc() as i64 + CORE_DATA_EPOCH;
+    let nanoseconds = (timestamp.fract() * 1e9) as u32;
+
+    Utc.timestamp_opt(seconds, nanoseconds)
+        .single()
+        .unwrap_or_default()
+        .with_timezone(&Local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::tests::{create_fixture_database, create_fixture_directory, utc_dates};
+
+    /// Builds the fixture databases from their SQL scripts, named like Apple Books does
+    fn create_fixture_databases() -> PathBuf {
+        let dir = create_fixture_directory("apple-books");
+
+        for (script, database) in [
+            (
+                "AEAnnotation.sql",
+                "AEAnnotation_v10312011_1727_local.sqlite",
+            ),
+            ("BKLibrary.sql", "BKLibrary-1-091020131601.sqlite"),
+        ] {
+            create_fixture_database(
+                &dir.join(database),
+                &Path::new("tests/data/apple_books").join(script),
+            );
+        }
+
+        dir
+    }
+
+    #[test]
+    fn test_cfi_spine_position() {
+        assert_eq!(
+            cfi_spine_position("epubcfi(/6/12[chapter2]!/4/2/6,/1:0,/1:120)"),
+            Some(12)
+        );
+        assert_eq!(cfi_spine_position("not a cfi"), None);
+    }
+
+    #[test]
+    fn test_core_data_date() {
+        assert_eq!(
+            core_data_date(0.0).with_timezone(&Utc).to_rfc3339(),
+            "2001-01-01T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_read_clips() {
+        let dir = create_fixture_databases();
+        let clips = AppleBooksDirectory::new(&dir)
+            .read_clips()
+            .expect("Could not read Apple Books databases");
+        fs::remove_dir_all(&dir).ok();
+
+        // Deleted annotations and reading positions are skipped
+        insta::assert_yaml_snapshot!(utc_dates(&clips));
+    }
+
+    #[test]
+    fn test_missing_databases() {
+        let source = AppleBooksDirectory::new(Path::new("tests/data"));
+
+        assert!(source.read_clips().is_err());
+    }
+}
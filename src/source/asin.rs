@@ -0,0 +1,297 @@
+use crate::clippings::matching_titles;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the Kindle book files
+const BOOK_EXTENSIONS: [&str; 5] = ["azw", "azw3", "mobi", "kfx", "pdf"];
+
+/// MOBI books have their metadata in the first record, which fits in this many bytes
+const MOBI_HEADER_SIZE: u64 = 64 * 1024;
+
+/// EXTH record types of the MOBI metadata
+const EXTH_ASIN: u32 = 113;
+const EXTH_CDE_ASIN: u32 = 504;
+const EXTH_TITLE: u32 = 503;
+
+/// Resolves the ASIN of the books, found in the book files of a Kindle `documents` directory
+///
+/// Book files are named after their title and ASIN, and MOBI and AZW3 books also have them in
+/// their metadata, which is preferred as file names get truncated. Personal documents have no
+/// ASIN, so they are not resolved.
+#[derive(Debug, Clone, Default)]
+pub struct AsinResolver {
+    /// Title and ASIN of the books, in path order
+    books: Vec<(String, String)>,
+}
+
+impl AsinResolver {
+    /// Scans a directory tree for book files
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The Kindle `documents` directory
+    ///
+    /// # Errors
+    /// Raise if the directory cannot be read, unreadable book files being skipped
+    pub fn scan(path: &Path) -> Result<Self> {
+        let mut book_files = Vec::new();
+        find_files(path, &BOOK_EXTENSIONS, &mut book_files)
+            .with_context(|| format!("Could not scan Kindle documents {path:?}"))?;
+
+        let mut books = Vec::new();
+        for book_file in book_files {
+            let stem = book_file.file_stem().unwrap_or_default().to_string_lossy();
+            let (file_title, file_asin) = split_file_name(&stem);
+
+            let (title, asin) = match read_mobi_metadata(&book_file) {
+                Ok(Some((title, asin))) => (title.unwrap_or(file_title), asin.or(file_asin)),
+                _ => (file_title, file_asin),
+            };
+
+            if let Some(asin) = asin {
+                books.push((title, asin));
+            }
+        }
+
+        Ok(Self { books })
+    }
+
+    /// Returns the title and ASIN of the books found
+    pub fn books(&self) -> &[(String, String)] {
+        &self.books
+    }
+
+    /// Returns the ASIN of a book, matching its name with the titles of the book files, see
+    /// `matching_titles`
+    pub fn resolve(&self, book_name: &str) -> Option<&str> {
+        matching_titles(&self.books, book_name)
+            .first()
+            .map(|(_, asin)| asin.as_str())
+    }
+}
+
+/// Returns the link opening a book at a location in the Kindle apps
+/// * Example: `kindle://book?action=open&asin=B00ZYVIVA0&location=877`
+pub fn kindle_link(asin: &str, location: usize) -> String {
+    format!("kindle://book?action=open&asin={asin}&location={location}")
+}
+
+/// Splits a Kindle book file name, without extension, into the book title and ASIN
+/// * Example: `Shoe Dog-asin_B00ZYVIVA0-type_EBOK-v_0` and `Shoe Dog_B00ZYVIVA0` give
+///   `("Shoe Dog", Some("B00ZYVIVA0"))`
+pub(crate) fn split_file_name(stem: &str) -> (String, Option<String>) {
+    let is_asin = |text: &str| {
+        text.len() == 10
+            && text
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    };
+
+    let (title, asin) = match stem.split_once("-asin_") {
+        Some((title, rest)) => (title, rest.split('-').next().filter(|asin| is_asin(asin))),
+        None => match stem.rsplit_once('_') {
+            Some((title, asin)) if is_asin(asin) => (title, Some(asin)),
+            _ => (stem, None),
+        },
+    };
+
+    (title.trim().to_string(), asin.map(str::to_string))
+}
+
+/// Collects the files of a directory with one of the extensions, recursively and sorted by path
+pub(crate) fn find_files(dir: &Path, extensions: &[&str], files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path());
+
+    // Symbolic links are not followed, to avoid loops
+    for entry in entries {
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            find_files(&path, extensions, files)?;
+        } else if file_type.is_file()
+            && matches!(path.extension(), Some(extension) if extensions.iter().any(|known| extension == *known))
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the title and ASIN of the EXTH metadata of a MOBI or AZW3 book
+/// # Returns
+/// * `Result<Option<(Option<String>, Option<String>)>>` - The title and ASIN, or nothing if the
+///   file is not a MOBI book with metadata
+/// # Errors
+/// Raise if the file cannot be read
+pub(crate) fn read_mobi_metadata(path: &Path) -> Result<Option<(Option<String>, Option<String>)>> {
+    let mut header = Vec::new();
+    fs::File::open(path)?
+        .take(MOBI_HEADER_SIZE)
+        .read_to_end(&mut header)?;
+
+    Ok(parse_exth(&header).map(|records| {
+        let record = |record_type| {
+            records
+                .iter()
+                .find(|(found_type, data)| *found_type == record_type && !data.trim().is_empty())
+                .map(|(_, data)| data.trim().to_string())
+        };
+
+        (
+            record(EXTH_TITLE),
+            record(EXTH_ASIN).or_else(|| record(EXTH_CDE_ASIN)),
+        )
+    }))
+}
+
+/// Returns the EXTH records of a MOBI file, which starts with a PalmDB header whose first
+/// record holds the MOBI header, followed by the EXTH header
+/// # Returns
+/// * `Option<Vec<(u32, String)>>` - The type and text of the records, or nothing if the file has
+///   no EXTH header
+fn parse_exth(file: &[u8]) -> Option<Vec<(u32, String)>> {
+    let u32_at = |offset: usize| -> Option<u32> {
+        file.get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    };
+
+    if file.get(60..68)? != b"BOOKMOBI" {
+        return None;
+    }
+
+    // The first entry of the record list, following the PalmDB header, is the first record
+    let record = u32_at(78)? as usize;
+    if file.get(record + 16..record + 20)? != b"MOBI" {
+        return None;
+    }
+
+    let mobi_header_length = u32_at(record + 20)? as usize;
+    let has_exth = u32_at(record + 128)? & 0x40 != 0;
+    let exth = record + 16 + mobi_header_length;
+    if !has_exth || file.get(exth..exth + 4)? != b"EXTH" {
+        return None;
+    }
+
+    let mut records = Vec::new();
+    let mut offset = exth + 12;
+    for _ in 0..u32_at(exth + 8)? {
+        let record_type = u32_at(offset)?;
+        // The length includes the type and length
+        let length = (u32_at(offset + 4)? as usize).max(8);
+        let data = file.get(offset + 8..offset + length)?;
+
+        records.push((record_type, String::from_utf8_lossy(data).into_owned()));
+        offset += length;
+    }
+
+    Some(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::tests::create_fixture_directory;
+
+    /// Encodes a MOBI file header with the given EXTH records
+    fn mobi_file(records: &[(u32, &str)]) -> Vec<u8> {
+        const MOBI_HEADER_LENGTH: usize = 232;
+        const RECORD: usize = 80;
+
+        let mut file = vec![0; RECORD + 16 + MOBI_HEADER_LENGTH];
+        file[60..68].copy_from_slice(b"BOOKMOBI");
+        file[78..82].copy_from_slice(&(RECORD as u32).to_be_bytes());
+        file[RECORD + 16..RECORD + 20].copy_from_slice(b"MOBI");
+        file[RECORD + 20..RECORD + 24].copy_from_slice(&(MOBI_HEADER_LENGTH as u32).to_be_bytes());
+        file[RECORD + 128..RECORD + 132].copy_from_slice(&0x50_u32.to_be_bytes());
+
+        let mut exth = Vec::new();
+        for (record_type, data) in records {
+            exth.extend(record_type.to_be_bytes());
+            exth.extend((data.len() as u32 + 8).to_be_bytes());
+            exth.extend(data.as_bytes());
+        }
+
+        file.extend(b"EXTH");
+        file.extend((exth.len() as u32 + 12).to_be_bytes());
+        file.extend((records.len() as u32).to_be_bytes());
+        file.extend(exth);
+        file
+    }
+
+    #[test]
+    fn test_split_file_name() {
+        assert_eq!(
+            split_file_name("Shoe Dog-asin_B00ZYVIVA0-type_EBOK-v_0"),
+            ("Shoe Dog".to_string(), Some("B00ZYVIVA0".to_string()))
+        );
+        assert_eq!(
+            split_file_name("Shoe Dog_B00ZYVIVA0"),
+            ("Shoe Dog".to_string(), Some("B00ZYVIVA0".to_string()))
+        );
+        assert_eq!(split_file_name("my_notes"), ("my_notes".to_string(), None));
+    }
+
+    #[test]
+    fn test_parse_exth() {
+        let file = mobi_file(&[
+            (100, "Phil Knight"),
+            (EXTH_TITLE, "Shoe Dog"),
+            (EXTH_ASIN, "B00ZYVIVA0"),
+        ]);
+
+        assert_eq!(
+            parse_exth(&file),
+            Some(vec![
+                (100, "Phil Knight".to_string()),
+                (EXTH_TITLE, "Shoe Dog".to_string()),
+                (EXTH_ASIN, "B00ZYVIVA0".to_string()),
+            ])
+        );
+        assert_eq!(parse_exth(b"not a MOBI file"), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = create_fixture_directory("asin");
+        fs::create_dir_all(dir.join("Downloads")).expect("Could not create fixture directory");
+
+        // Older Kindles name the books by ASIN only, the title being in the metadata
+        fs::write(
+            dir.join("B09LVVN9L3_EBOK.azw"),
+            mobi_file(&[
+                (EXTH_TITLE, "Trillions: How a Band of Wall Street Renegades"),
+                (EXTH_CDE_ASIN, "B09LVVN9L3"),
+            ]),
+        )
+        .unwrap();
+        fs::write(dir.join("Downloads/Shoe Dog_B00ZYVIVA0.kfx"), "").unwrap();
+        fs::write(dir.join("Dune Messiah_B00B7NPRY8.kfx"), "").unwrap();
+        fs::write(dir.join("Dune Messiah_B00B7NPRYA.kfx"), "").unwrap();
+        fs::write(dir.join("Dune_B00B7NPRY9.kfx"), "").unwrap();
+        fs::write(dir.join("My Clippings.txt"), "").unwrap();
+
+        let resolver = AsinResolver::scan(&dir).expect("Could not scan documents");
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(resolver.resolve("Shoe Dog"), Some("B00ZYVIVA0"));
+        assert_eq!(
+            resolver
+                .resolve("Trillions: How a Band of Wall Street Renegades Invented the Index Fund"),
+            Some("B09LVVN9L3")
+        );
+        assert_eq!(resolver.resolve("Building a Second Brain"), None);
+
+        // The exact title is preferred to the titles it starts, which are too many to guess
+        assert_eq!(resolver.resolve("Dune"), Some("B00B7NPRY9"));
+        assert_eq!(
+            resolver.resolve("Dune Messiah (Dune Chronicles, Book 2)"),
+            Some("B00B7NPRY8")
+        );
+        assert_eq!(resolver.resolve("Du"), None);
+    }
+}
@@ -0,0 +1,419 @@
+use crate::clippings::{author_names, is_same_title, BookClips, BookMetadata};
+use crate::source::notebook::decode_entities;
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Minimal share of title words in common for titles to be the same book, see `match_score`
+const MIN_TITLE_SIMILARITY: f64 = 0.75;
+
+/// Book of a Calibre library
+#[derive(Debug, Clone)]
+struct CalibreBook {
+    title: String,
+    /// Authors joined with ` & `, like Calibre shows them
+    authors: String,
+    /// Folder of the book, relative to the library
+    path: String,
+    has_cover: bool,
+    metadata: BookMetadata,
+}
+
+/// Calibre library, whose `metadata.db` database and `metadata.opf` files describe the books
+///
+/// The library is not a clipping source, it enriches the books of the other sources with their
+/// series, tags, publisher, ISBN, language, publication date and cover.
+pub struct CalibreLibrary {
+    path: PathBuf,
+}
+
+impl CalibreLibrary {
+    /// # Arguments
+    ///
+    /// * `path` - The library directory, holding `metadata.db`
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Sets the metadata of the books found in the library, matched by title and author
+    ///
+    /// # Arguments
+    ///
+    /// * `books_clips` - The books to enrich, whose metadata is replaced when found
+    ///
+    /// # Returns
+    /// The number of books found in the library
+    ///
+    /// # Errors
+    /// Raise if the database cannot be read, unreadable OPF files being skipped
+    pub fn enrich(&self, books_clips: &mut [BookClips]) -> Result<usize> {
+        let library = self.read_books()?;
+        let mut found = 0;
+
+        for book in books_clips.iter_mut() {
+            let best = library
+                .iter()
+                .filter_map(|calibre_book| {
+                    match_score(&book.book_name, &author_names(&book.authors), calibre_book)
+                        .map(|score| (score, calibre_book))
+                })
+                // The first book wins ties, as `max_by` would keep the last one
+                .fold(
+                    None,
+                    |best: Option<(f64, &CalibreBook)>, (score, calibre_book)| match best {
+                        Some((best_score, _)) if best_score >= score => best,
+                        _ => Some((score, calibre_book)),
+                    },
+                );
+
+            if let Some((_, calibre_book)) = best {
+                book.metadata = Some(self.book_metadata(calibre_book));
+                found += 1;
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Completes the metadata of the database with the OPF file and cover of the book folder
+    fn book_metadata(&self, calibre_book: &CalibreBook) -> BookMetadata {
+        let folder = self.path.join(&calibre_book.path);
+        let mut metadata = calibre_book.metadata.clone();
+
+        if let Ok(opf) = fs::read_to_string(folder.join("metadata.opf")) {
+            fill_missing(&mut metadata, parse_opf(&opf));
+        }
+
+        let cover = folder.join("cover.jpg");
+        if calibre_book.has_cover && cover.is_file() {
+            metadata.cover = Some(cover);
+        }
+
+        metadata
+    }
+
+    /// Reads the books of the database, in identifier order
+    fn read_books(&self) -> Result<Vec<CalibreBook>> {
+        let db_path = self.path.join("metadata.db");
+        let connection = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Could not open Calibre library {db_path:?}"))?;
+
+        let mut statement = connection.prepare(
+            "SELECT l.book, t.name FROM books_tags_link l
+             JOIN tags t ON t.id = l.tag
+             ORDER BY l.book, t.name",
+        )?;
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        for row in statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            let (book, tag) = row?;
+            tags.entry(book).or_default().push(tag);
+        }
+
+        let mut statement = connection.prepare(
+            "SELECT b.id, b.title, b.path, b.has_cover, b.pubdate, b.series_index, b.isbn,
+                 (SELECT group_concat(name, ' & ') FROM (
+                     SELECT a.name FROM books_authors_link l
+                     JOIN authors a ON a.id = l.author
+                     WHERE l.book = b.id ORDER BY l.id)),
+                 (SELECT s.name FROM books_series_link l
+                     JOIN series s ON s.id = l.series WHERE l.book = b.id),
+                 (SELECT p.name FROM books_publishers_link l
+                     JOIN publishers p ON p.id = l.publisher WHERE l.book = b.id),
+                 (SELECT g.lang_code FROM books_languages_link l
+                     JOIN languages g ON g.id = l.lang_code
+                     WHERE l.book = b.id ORDER BY l.item_order LIMIT 1),
+                 (SELECT val FROM identifiers WHERE book = b.id AND type = 'isbn')
+             FROM books b
+             ORDER BY b.id",
+        )?;
+
+        let books = statement
+            .query_map([], |row| {
+                let text = |idx| -> rusqlite::Result<Option<String>> {
+                    Ok(row
+                        .get::<_, Option<String>>(idx)?
+                        .map(|text| text.trim().to_string())
+                        .filter(|text| !text.is_empty()))
+                };
+                let series = text(8)?;
+
+                Ok(CalibreBook {
+                    title: text(1)?.unwrap_or_default(),
+                    authors: text(7)?.unwrap_or_default(),
+                    path: text(2)?.unwrap_or_default(),
+                    has_cover: row.get::<_, Option<bool>>(3)?.unwrap_or_default(),
+                    metadata: BookMetadata {
+                        // Books out of a series still have an index
+                        series_index: series.as_ref().and(row.get::<_, Option<f64>>(5)?),
+                        series,
+                        tags: tags.remove(&row.get::<_, i64>(0)?).unwrap_or_default(),
+                        publisher: text(9)?,
+                        isbn: text(11)?.or(text(6)?),
+                        language: text(10)?,
+                        published: text(4)?.as_deref().and_then(parse_date),
+                        cover: None,
+                    },
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(books)
+    }
+}
+
+/// Returns how well a library book matches a book of the clips, from 0 to 1
+/// # Returns
+/// * `Option<f64>` - The score, or nothing if the titles differ, or both books have authors
+///   without a word in common
+fn match_score(book_name: &str, author: &str, calibre_book: &CalibreBook) -> Option<f64> {
+    if !author.trim().is_empty()
+        && !calibre_book.authors.is_empty()
+        && words(author).is_disjoint(&words(&calibre_book.authors))
+    {
+        return None;
+    }
+
+    let (title_words, calibre_words) = (words(book_name), words(&calibre_book.title));
+    let similarity = title_words.intersection(&calibre_words).count() as f64
+        / title_words.union(&calibre_words).count().max(1) as f64;
+
+    if similarity == 1.0 {
+        Some(1.0)
+    } else if is_same_title(book_name, &calibre_book.title) {
+        // Clippings titles are often truncated, or lack the subtitle
+        Some(similarity.max(MIN_TITLE_SIMILARITY))
+    } else if similarity >= MIN_TITLE_SIMILARITY {
+        Some(similarity)
+    } else {
+        None
+    }
+}
+
+/// Returns the lowercase words of a text, ignoring punctuation
+fn words(text: &str) -> BTreeSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Parses a Calibre date, like `2016-04-26 04:00:00+00:00` or `2016-04-26T04:00:00+00:00`
+///
+/// Calibre marks unknown dates with the year 101.
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    date.get(..10)
+        .and_then(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok())
+        .filter(|date| date.year() >= 1000)
+}
+
+/// Sets the missing fields of `metadata` to those of `other`
+fn fill_missing(metadata: &mut BookMetadata, other: BookMetadata) {
+    if metadata.series.is_none() {
+        metadata.series = other.series;
+        metadata.series_index = metadata.series_index.or(other.series_index);
+    }
+    if metadata.tags.is_empty() {
+        metadata.tags = other.tags;
+    }
+    metadata.publisher = metadata.publisher.take().or(other.publisher);
+    metadata.isbn = metadata.isbn.take().or(other.isbn);
+    metadata.language = metadata.language.take().or(other.language);
+    metadata.published = metadata.published.or(other.published);
+    metadata.cover = metadata.cover.take().or(other.cover);
+}
+
+/// Reads the metadata of a Calibre `metadata.opf` file
+///
+/// OPF files are simple enough for the elements to be found without an XML parser.
+fn parse_opf(opf: &str) -> BookMetadata {
+    let first = |name| {
+        opf_elements(opf, name)
+            .into_iter()
+            .map(|(_, text)| text)
+            .find(|text| !text.is_empty())
+    };
+
+    let mut metadata = BookMetadata {
+        tags: opf_elements(opf, "dc:subject")
+            .into_iter()
+            .map(|(_, text)| text)
+            .filter(|text| !text.is_empty())
+            .collect(),
+        publisher: first("dc:publisher"),
+        isbn: opf_elements(opf, "dc:identifier")
+            .into_iter()
+            .find(|(attributes, _)| {
+                matches!(attribute(attributes, "opf:scheme"), Some(scheme) if scheme.eq_ignore_ascii_case("isbn"))
+            })
+            .map(|(_, text)| text),
+        language: first("dc:language"),
+        published: first("dc:date").as_deref().and_then(parse_date),
+        ..Default::default()
+    };
+
+    for attributes in opf.split("<meta ").skip(1) {
+        let attributes = attributes.split('>').next().unwrap_or_default();
+        match attribute(attributes, "name").as_deref() {
+            Some("calibre:series") => metadata.series = attribute(attributes, "content"),
+            Some("calibre:series_index") => {
+                metadata.series_index =
+                    attribute(attributes, "content").and_then(|index| index.parse().ok())
+            }
+            _ => {}
+        }
+    }
+
+    metadata
+}
+
+/// Returns the attributes and decoded text of the elements named `name`
+fn opf_elements(opf: &str, name: &str) -> Vec<(String, String)> {
+    let (start, end) = (format!("<{name}"), format!("</{name}>"));
+
+    opf.split(&start)
+        .skip(1)
+        .filter_map(|element| {
+            let (attributes, rest) = element.split_once('>')?;
+            // Skips longer names starting with the same prefix, and empty elements
+            if !(attributes.is_empty() || attributes.starts_with(char::is_whitespace))
+                || attributes.ends_with('/')
+            {
+                return None;
+            }
+            let (text, _) = rest.split_once(&end)?;
+            Some((attributes.to_string(), decode_entities(text.trim())))
+        })
+        .collect()
+}
+
+/// Returns the decoded value of an attribute of an element
+pub(crate) fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let pattern = format!("{name}=");
+    let (idx, _) = attributes
+        .match_indices(&pattern)
+        .find(|(idx, _)| *idx == 0 || attributes[..*idx].ends_with(char::is_whitespace))?;
+
+    let value = &attributes[idx + pattern.len()..];
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    value[1..]
+        .split(quote)
+        .next()
+        .map(|value| decode_entities(value.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use crate::source::tests::{create_fixture_database, create_fixture_directory};
+
+    /// Copies a fixture directory, recursively
+    fn copy_dir(from: &Path, to: &Path) {
+        fs::create_dir_all(to).expect("Could not create fixture directory");
+        for entry in fs::read_dir(from).expect("Fixture not found") {
+            let path = entry.unwrap().path();
+            let target = to.join(path.file_name().unwrap());
+            if path.is_dir() {
+                copy_dir(&path, &target);
+            } else {
+                fs::copy(&path, &target).expect("Could not copy fixture");
+            }
+        }
+    }
+
+    #[test]
+    fn test_enrich() {
+        let dir = create_fixture_directory("calibre");
+        copy_dir(Path::new("tests/data/calibre"), &dir);
+        create_fixture_database(&dir.join("metadata.db"), &dir.join("metadata.sql"));
+
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let mut books_clips = parse_clips(input.as_str());
+        let found = CalibreLibrary::new(&dir)
+            .enrich(&mut books_clips)
+            .expect("Could not read library");
+        fs::remove_dir_all(&dir).ok();
+
+        // Trillions of the library is by another author
+        assert_eq!(found, 3);
+        let metadata: Vec<(&str, Option<BookMetadata>)> = books_clips
+            .iter()
+            .map(|book| {
+                let mut metadata = book.metadata.clone();
+                // Covers are in the temporary directory
+                if let Some(metadata) = &mut metadata {
+                    metadata.cover = metadata
+                        .cover
+                        .as_ref()
+                        .map(|cover| cover.strip_prefix(&dir).unwrap_or(cover).to_path_buf());
+                }
+                (book.book_name.as_str(), metadata)
+            })
+            .collect();
+        insta::assert_yaml_snapshot!(metadata);
+    }
+
+    #[test]
+    fn test_match_score() {
+        let book = |title: &str, authors: &str| CalibreBook {
+            title: title.to_string(),
+            authors: authors.to_string(),
+            path: String::new(),
+            has_cover: false,
+            metadata: BookMetadata::default(),
+        };
+
+        assert_eq!(
+            match_score("Shoe Dog", "Phil Knight", &book("Shoe Dog", "")),
+            Some(1.0)
+        );
+        assert_eq!(
+            match_score(
+                "Shoe Dog",
+                "Phil Knight",
+                &book("Shoe Dog: A Memoir by the Creator of Nike", "Phil Knight")
+            ),
+            Some(MIN_TITLE_SIMILARITY)
+        );
+        assert_eq!(
+            match_score("Shoe Dog", "Phil Knight", &book("Shoe Dog", "Jane Doe")),
+            None
+        );
+        assert_eq!(
+            match_score("Shoe Dog", "", &book("Dog Days", "Phil Knight")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_opf() {
+        let opf = r#"<metadata>
+            <dc:identifier opf:scheme="calibre">1</dc:identifier>
+            <dc:identifier opf:scheme="ISBN">9781501135910</dc:identifier>
+            <dc:identifier-extra>not an identifier</dc:identifier-extra>
+            <dc:publisher>Simon &amp; Schuster</dc:publisher>
+            <dc:date>0101-01-01T00:00:00+00:00</dc:date>
+            <dc:subject>Biography</dc:subject>
+            <dc:subject/>
+            <meta name="calibre:series" content="Nike &amp; Co"/>
+            <meta content="2.5" name="calibre:series_index"/>
+        </metadata>"#;
+
+        assert_eq!(
+            parse_opf(opf),
+            BookMetadata {
+                series: Some("Nike & Co".to_string()),
+                series_index: Some(2.5),
+                tags: vec!["Biography".to_string()],
+                publisher: Some("Simon & Schuster".to_string()),
+                isbn: Some("9781501135910".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+}
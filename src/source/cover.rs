@@ -0,0 +1,114 @@
+use crate::clippings::{matching_titles, BookClips};
+use crate::source::asin::AsinResolver;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Cover thumbnails cached by the Kindle in `system/thumbnails`, matched to the books by ASIN
+///
+/// Thumbnails are named like `thumbnail_B00ZYVIVA0_EBOK_portrait.jpg`, so books need an ASIN,
+/// see [`AsinResolver`].
+#[derive(Debug, Clone, Default)]
+pub struct BookCovers {
+    /// Title of the books and path of their cover
+    covers: Vec<(String, PathBuf)>,
+}
+
+impl BookCovers {
+    /// Finds the thumbnails of the books
+    ///
+    /// # Arguments
+    ///
+    /// * `thumbnails_dir` - The Kindle `system/thumbnails` directory
+    /// * `asins` - The ASIN of the books
+    ///
+    /// # Errors
+    /// Raise if the directory cannot be read
+    pub fn scan(thumbnails_dir: &Path, asins: &AsinResolver) -> Result<Self> {
+        let mut thumbnails: Vec<PathBuf> = fs::read_dir(thumbnails_dir)
+            .with_context(|| format!("Could not read thumbnails directory {thumbnails_dir:?}"))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()?;
+        thumbnails.sort();
+
+        let covers = asins
+            .books()
+            .iter()
+            .filter_map(|(title, asin)| {
+                let prefix = format!("thumbnail_{asin}_");
+                thumbnails
+                    .iter()
+                    .find(|thumbnail| {
+                        let file_name = thumbnail.file_name().unwrap_or_default().to_string_lossy();
+                        file_name.starts_with(&prefix) && file_name.ends_with("_portrait.jpg")
+                    })
+                    .map(|thumbnail| (title.clone(), thumbnail.clone()))
+            })
+            .collect();
+
+        Ok(Self { covers })
+    }
+
+    /// Returns the cover of a book, matching its name with the titles of the book files, see
+    /// `matching_titles`
+    pub fn resolve(&self, book_name: &str) -> Option<&Path> {
+        matching_titles(&self.covers, book_name)
+            .first()
+            .map(|(_, cover)| cover.as_path())
+    }
+
+    /// Returns the cover of a book, preferring the cover of its library metadata to the thumbnails
+    pub fn cover_of<'a>(&'a self, book: &'a BookClips) -> Option<&'a Path> {
+        book.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.cover.as_deref())
+            .or_else(|| self.resolve(&book.book_name))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::source::tests::create_fixture_directory;
+
+    /// Returns covers with a fake thumbnail for Shoe Dog, with the directory to remove after use
+    pub(crate) fn get_test_covers() -> (BookCovers, PathBuf) {
+        let dir = create_fixture_directory("covers");
+
+        let documents = dir.join("documents");
+        let thumbnails = dir.join("system/thumbnails");
+        fs::create_dir_all(&documents).expect("Could not create fixture directory");
+        fs::create_dir_all(&thumbnails).expect("Could not create fixture directory");
+
+        fs::write(documents.join("Shoe Dog_B00ZYVIVA0.kfx"), "").unwrap();
+        fs::write(documents.join("Trillions_B08TX2DKP2.kfx"), "").unwrap();
+        fs::write(
+            thumbnails.join("thumbnail_B00ZYVIVA0_EBOK_portrait.jpg"),
+            b"\xff\xd8\xff\xe0 not really a JPEG",
+        )
+        .unwrap();
+        fs::write(
+            thumbnails.join("thumbnail_B08TX2DKP2_EBOK_landscape.jpg"),
+            "",
+        )
+        .unwrap();
+
+        let asins = AsinResolver::scan(&documents).expect("Could not scan documents");
+        let covers = BookCovers::scan(&thumbnails, &asins).expect("Could not scan thumbnails");
+
+        (covers, dir)
+    }
+
+    #[test]
+    fn test_resolve() {
+        let (covers, dir) = get_test_covers();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(
+            covers.resolve("Shoe Dog").and_then(Path::file_name),
+            Some("thumbnail_B00ZYVIVA0_EBOK_portrait.jpg".as_ref())
+        );
+        // Only portrait thumbnails are covers
+        assert_eq!(covers.resolve("Trillions"), None);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::clippings::{parse_clip_list, Clip};
+use crate::source::ClippingSource;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Kindle `My Clippings.txt` file
+///
+/// Only the clips after the last `#==========` marker are read, see `parse_clips`
+pub struct KindleClippings {
+    path: PathBuf,
+}
+
+impl KindleClippings {
+    /// # Arguments
+    ///
+    /// * `path` - The location of the clippings file
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl ClippingSource for KindleClippings {
+    fn name(&self) -> &str {
+        "Kindle clippings"
+    }
+
+    fn read_clips(&self) -> Result<Vec<Clip>> {
+        let input = fs::read_to_string(&self.path)
+            .with_context(|| format!("Clippings file not found: {:?}", self.path))?;
+
+        Ok(parse_clip_list(input.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::koreader::KoreaderDirectory;
+    use crate::source::read_books;
+
+    #[test]
+    fn test_read_books() {
+        let sources: Vec<Box<dyn ClippingSource>> = vec![Box::new(KindleClippings::new(
+            Path::new("tests/data/clippings.txt"),
+        ))];
+
+        let books_clips = read_books(&sources);
+        let input = fs::read_to_string("tests/data/clippings.txt").unwrap();
+
+        // Same result as parsing the file directly
+        assert_eq!(
+            serde_json::to_string(&books_clips).unwrap(),
+            serde_json::to_string(&crate::clippings::parse_clips(&input)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let source = KindleClippings::new(Path::new("tests/data/missing.txt"));
+
+        assert!(source.read_clips().is_err());
+    }
+
+    #[test]
+    fn test_read_books_missing_file() {
+        let koreader = || -> Box<dyn ClippingSource> {
+            Box::new(KoreaderDirectory::new(Path::new("tests/data/koreader")))
+        };
+        let sources = vec![
+            Box::new(KindleClippings::new(Path::new("tests/data/missing.txt"))),
+            koreader(),
+        ];
+
+        let books_clips = read_books(&sources);
+
+        // The missing clippings file is skipped and the KOReader clips are still read
+        assert!(!books_clips.is_empty());
+        assert_eq!(
+            serde_json::to_string(&books_clips).unwrap(),
+            serde_json::to_string(&read_books(&[koreader()])).unwrap()
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use crate::clippings::{parse_authors, Clip, ClipKind};
+use crate::source::ClippingSource;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use rusqlite::{params, Connection, OpenFlags};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Kobo has no locations, so clips are located by chapter index and progress, this many per chapter
+const LOCATIONS_PER_CHAPTER: f64 = 10_000.0;
+
+/// Progress is rounded to this many locations, the bookmarks saved at the same place taking the
+/// locations in between
+const LOCATIONS_PER_STEP: usize = 10;
+
+/// Kobo `.kobo/KoboReader.sqlite` database
+///
+/// Highlights are read from the `Bookmark` table, and books and chapters from the `content` table.
+/// The database is opened read-only, so it can be read from a mounted device.
+pub struct KoboDatabase {
+    path: PathBuf,
+}
+
+impl KoboDatabase {
+    /// # Arguments
+    ///
+    /// * `path` - The location of the `KoboReader.sqlite` file
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+/// Row of the `Bookmark` table, joined with its book
+struct KoboBookmark {
+    volume_id: String,
+    content_id: String,
+    text: Option<String>,
+    annotation: Option<String>,
+    chapter_progress: f64,
+    date_created: Option<String>,
+    kind: Option<String>,
+    hidden: bool,
+    title: Option<String>,
+    author: Option<String>,
+}
+
+/// Chapter of a book, from the `content` table
+struct KoboChapter {
+    content_id: String,
+    title: Option<String>,
+}
+
+impl ClippingSource for KoboDatabase {
+    fn name(&self) -> &str {
+        "Kobo"
+    }
+
+    fn read_clips(&self) -> Result<Vec<Clip>> {
+        let connection = Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Could not open Kobo database {:?}", self.path))?;
+
+        // Hidden bookmarks are the ones deleted on the device
+        let mut statement = connection.prepare(
+            "SELECT b.VolumeID, b.ContentID, b.Text, b.Annotation, b.ChapterProgress,
+                    b.DateCreated, b.Type, b.Hidden IN ('true', 1), book.Title, book.Attribution
+             FROM Bookmark b
+             LEFT JOIN content book ON book.ContentID = b.VolumeID
+             ORDER BY b.DateCreated, b.BookmarkID",
+        )?;
+        let bookmarks = statement
+            .query_map([], |row| {
+                Ok(KoboBookmark {
+                    volume_id: row.get(0)?,
+                    content_id: row.get(1)?,
+                    text: row.get(2)?,
+                    annotation: row.get(3)?,
+                    chapter_progress: row.get(4)?,
+                    date_created: row.get(5)?,
+                    kind: row.get(6)?,
+                    hidden: row.get(7)?,
+                    title: row.get(8)?,
+                    author: row.get(9)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut chapters_statement = connection.prepare(
+            "SELECT ContentID, Title FROM content
+             WHERE ContentType = '9' AND BookID = ?1
+             ORDER BY VolumeIndex",
+        )?;
+
+        let mut clips = Vec::new();
+        // Number of bookmarks saved at each place of each book
+        let mut saved_at: HashMap<(String, usize), usize> = HashMap::new();
+        for bookmark in bookmarks {
+            let chapters = chapters_statement
+                .query_map(params![bookmark.volume_id], |row| {
+                    Ok(KoboChapter {
+                        content_id: row.get(0)?,
+                        title: row.get(1)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            // Bookmarks saved at the same place are numbered by creation order, which new and
+            // deleted bookmarks do not change
+            let (chapter_index, position) = bookmark.position(&chapters);
+            let step = (position * LOCATIONS_PER_CHAPTER / LOCATIONS_PER_STEP as f64).round();
+            let location = step as usize * LOCATIONS_PER_STEP;
+            let count = saved_at
+                .entry((bookmark.volume_id.clone(), location))
+                .or_default();
+            let location = location + (*count).min(LOCATIONS_PER_STEP - 1);
+            *count += 1;
+
+            if bookmark.hidden {
+                continue;
+            }
+
+            match bookmark.to_clips(&chapters, chapter_index, position, location) {
+                Ok(bookmark_clips) => clips.extend(bookmark_clips),
+                Err(err) => eprintln!("Skipping Kobo bookmark of {:?}: {err:#}", bookmark.title),
+            }
+        }
+
+        Ok(clips)
+    }
+}
+
+impl KoboBookmark {
+    /// Returns the index of the chapter of the bookmark, and its position in the book counted
+    /// in chapters
+    fn position(&self, chapters: &[KoboChapter]) -> (Option<usize>, f64) {
+        // Chapter entries are the bookmark content ID followed by an index, like `...xhtml-1`
+        let chapter_index = chapters
+            .iter()
+            .position(|chapter| chapter.content_id.starts_with(&self.content_id));
+
+        (
+            chapter_index,
+            chapter_index.unwrap_or_default() as f64 + self.chapter_progress,
+        )
+    }
+
+    /// Maps the bookmark to its clips, a highlight with an annotation giving a highlight and a note
+    fn to_clips(
+        &self,
+        chapters: &[KoboChapter],
+        chapter_index: Option<usize>,
+        position: f64,
+        location: usize,
+    ) -> Result<Vec<Clip>> {
+        let base = Clip {
+            book: self.title.clone().unwrap_or_else(|| self.volume_id.clone()),
+            authors: parse_authors(self.author.as_deref().unwrap_or_default()),
+            source_author: self.author.clone().unwrap_or_default(),
+            kind: ClipKind::Highlight,
+            content: String::new(),
+            date: parse_kobo_date(self.date_created.as_deref().unwrap_or_default())?,
+            location: (location, location),
+            page: None,
+            chapter: chapter_index.and_then(|idx| chapters[idx].title.clone()),
+            progress: chapter_index.map(|_| position / chapters.len() as f64),
+            color: None,
+            truncated: false,
+            approximate_location: false,
+        };
+
+        let text = self.text.as_deref().unwrap_or_default().trim();
+        let annotation = self.annotation.as_deref().unwrap_or_default().trim();
+
+        let mut clips = Vec::new();
+        match self.kind.as_deref() {
+            Some("highlight" | "note") => {
+                clips.push(Clip {
+                    content: text.to_string(),
+                    ..base.clone()
+                });
+
+                if !annotation.is_empty() {
+                    clips.push(Clip {
+                        kind: ClipKind::Note,
+                        content: annotation.to_string(),
+                        ..base
+                    });
+                }
+            }
+            Some("dogear") => clips.push(Clip {
+                kind: ClipKind::Bookmark,
+                ..base
+            }),
+            _ => {}
+        }
+
+        Ok(clips)
+    }
+}
+
+/// Parses a Kobo date, saved in UTC
+/// * Example: `2023-02-13T00:29:40.000` or `2023-02-14T21:02:11Z`
+fn parse_kobo_date(date: &str) -> Result<DateTime<Local>> {
+    let naive = NaiveDateTime::parse_from_str(date.trim_end_matches('Z'), "%Y-%m-%dT%H:%M:%S%.f")
+        .with_context(|| format!("Invalid Kobo date {date:?}"))?;
+
+    Ok(Utc.from_utc_datetime(&naive).with_timezone(&Local))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::tests::{create_fixture_database, create_fixture_directory, utc_dates};
+    use std::fs;
+
+    #[test]
+    fn test_parse_kobo_date() {
+        assert_eq!(
+            parse_kobo_date("2023-02-14T21:02:11Z").unwrap(),
+            parse_kobo_date("2023-02-14T21:02:11.000").unwrap()
+        );
+        assert!(parse_kobo_date("").is_err());
+    }
+
+    #[test]
+    fn test_read_clips() {
+        let dir = create_fixture_directory("kobo");
+        let path = dir.join("KoboReader.sqlite");
+        create_fixture_database(&path, Path::new("tests/data/kobo.sql"));
+        let clips = KoboDatabase::new(&path)
+            .read_clips()
+            .expect("Could not read Kobo database");
+        fs::remove_dir_all(&dir).ok();
+
+        // The hidden highlight and the highlight without date are skipped, the note annotation
+        // gives its own clip, and the second highlight of the first page is located after the
+        // first one
+        insta::assert_yaml_snapshot!(utc_dates(&clips));
+    }
+
+    #[test]
+    fn test_missing_database() {
+        let source = KoboDatabase::new(Path::new("tests/data/missing.sqlite"));
+
+        assert!(source.read_clips().is_err());
+    }
+}
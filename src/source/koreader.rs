@@ -0,0 +1,325 @@
+mod lua;
+
+use crate::clippings::{parse_authors, Clip, ClipKind};
+use crate::source::ClippingSource;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use lua::{parse_lua, LuaValue};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// KOReader has no locations, so clips are located by page and creation order, this many per page
+const LOCATIONS_PER_PAGE: usize = 100;
+
+/// Directory tree containing KOReader books and their `*.sdr/metadata.*.lua` sidecar files
+///
+/// Annotations are read from the `annotations` table written since KOReader 2024.01, or from the
+/// `highlight` and `bookmarks` tables of the older versions. Sidecar files that cannot be parsed
+/// are skipped with a warning, so they do not block the other books.
+pub struct KoreaderDirectory {
+    path: PathBuf,
+}
+
+impl KoreaderDirectory {
+    /// # Arguments
+    ///
+    /// * `path` - The root of the directory tree to scan, like the KOReader home folder
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl ClippingSource for KoreaderDirectory {
+    fn name(&self) -> &str {
+        "KOReader"
+    }
+
+    fn read_clips(&self) -> Result<Vec<Clip>> {
+        let mut metadata_files = Vec::new();
+        find_metadata_files(&self.path, &mut metadata_files)
+            .with_context(|| format!("Could not scan KOReader directory {:?}", self.path))?;
+
+        let mut clips = Vec::new();
+        for metadata_file in metadata_files {
+            match read_metadata_file(&metadata_file) {
+                Ok(file_clips) => clips.extend(file_clips),
+                Err(err) => eprintln!("Skipping {metadata_file:?}: {err:#}"),
+            }
+        }
+
+        Ok(clips)
+    }
+}
+
+/// Collects the `metadata.*.lua` files of the `*.sdr` directories, recursively and sorted by path
+fn find_metadata_files(dir: &Path, metadata_files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path());
+
+    let is_sdr = matches!(dir.extension(), Some(extension) if extension == "sdr");
+
+    // Symbolic links are not followed, to avoid loops
+    for entry in entries {
+        let file_type = entry.file_type()?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+
+        if file_type.is_dir() {
+            find_metadata_files(&entry.path(), metadata_files)?;
+        } else if is_sdr
+            && file_type.is_file()
+            && file_name.starts_with("metadata.")
+            && file_name.ends_with(".lua")
+        {
+            metadata_files.push(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the clips of a sidecar file
+///
+/// # Errors
+/// Raise if the file cannot be read or parsed
+fn read_metadata_file(path: &Path) -> Result<Vec<Clip>> {
+    let metadata = parse_lua(&fs::read_to_string(path)?)?;
+
+    // The book file is named like the sidecar directory, which is the fallback title
+    let file_title = path
+        .parent()
+        .and_then(Path::file_stem)
+        .map(|stem| Path::new(stem).file_stem().unwrap_or(stem))
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+
+    let book = document_property(&metadata, "title").unwrap_or(file_title);
+    // Several authors are written on separate lines
+    let source_author = document_property(&metadata, "authors")
+        .map(|authors| authors.lines().collect::<Vec<_>>().join("; "))
+        .unwrap_or_default();
+    let authors = parse_authors(&source_author);
+    let page_count = metadata.get("doc_pages").and_then(LuaValue::as_f64);
+
+    let legacy_annotations = match metadata.get("annotations") {
+        Some(_) => Vec::new(),
+        None => legacy_annotations(&metadata),
+    };
+
+    let mut annotations = Vec::new();
+    for annotation in metadata
+        .get("annotations")
+        .map(LuaValue::values)
+        .unwrap_or_default()
+        .into_iter()
+        .chain(&legacy_annotations)
+    {
+        let date = annotation
+            .get("datetime")
+            .and_then(LuaValue::as_str)
+            .context("Annotation without datetime")
+            .and_then(parse_koreader_date);
+        match date {
+            Ok(date) => annotations.push((annotation_page(annotation), date, annotation)),
+            Err(err) => eprintln!("Skipping an annotation of {path:?}: {err:#}"),
+        }
+    }
+
+    // Annotations of a page are numbered by creation order, which new annotations do not change
+    annotations.sort_by_key(|(page, date, _)| (*page, *date));
+
+    let mut clips = Vec::new();
+    let mut page_index = 0;
+    for (idx, (page, date, annotation)) in annotations.iter().enumerate() {
+        page_index = match idx.checked_sub(1).map(|previous| annotations[previous].0) {
+            Some(previous_page) if previous_page == *page => page_index + 1,
+            _ => 0,
+        };
+
+        let location = page.unwrap_or_default() * LOCATIONS_PER_PAGE + page_index;
+        let text = |key| {
+            annotation
+                .get(key)
+                .and_then(LuaValue::as_str)
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+
+        let base = Clip {
+            book: book.clone(),
+            authors: authors.clone(),
+            source_author: source_author.clone(),
+            kind: ClipKind::Highlight,
+            content: String::new(),
+            date: *date,
+            location: (location, location),
+            page: *page,
+            chapter: annotation
+                .get("chapter")
+                .and_then(LuaValue::as_str)
+                .map(str::to_string),
+            progress: page
+                .zip(page_count)
+                .map(|(page, page_count)| page as f64 / page_count),
+            color: annotation
+                .get("color")
+                .and_then(LuaValue::as_str)
+                .map(str::to_string),
+            truncated: false,
+            approximate_location: false,
+        };
+
+        // Page bookmarks have no highlighted position
+        if annotation.get("pos0").is_none() {
+            clips.push(Clip {
+                kind: ClipKind::Bookmark,
+                ..base
+            });
+            continue;
+        }
+
+        clips.push(Clip {
+            content: text("text"),
+            ..base.clone()
+        });
+
+        let note = text("note");
+        if !note.is_empty() {
+            clips.push(Clip {
+                kind: ClipKind::Note,
+                content: note,
+                ..base
+            });
+        }
+    }
+
+    Ok(clips)
+}
+
+/// Returns the annotations of the layout written before KOReader 2024.01, in the current layout
+///
+/// Highlights were saved by page in the `highlight` table, and their notes in the `bookmarks`
+/// table, saved at the same time as the highlight. The other bookmarks are page bookmarks.
+fn legacy_annotations(metadata: &LuaValue) -> Vec<LuaValue> {
+    let key = |key: &str| LuaValue::String(key.to_string());
+    let bookmarks = metadata
+        .get("bookmarks")
+        .map(LuaValue::values)
+        .unwrap_or_default();
+    let is_highlighted =
+        |bookmark: &LuaValue| bookmark.get("highlighted") == Some(&LuaValue::Boolean(true));
+
+    let mut annotations = Vec::new();
+    for (page, highlights) in metadata
+        .get("highlight")
+        .map(LuaValue::entries)
+        .unwrap_or_default()
+    {
+        for highlight in highlights.values() {
+            let mut fields = highlight.entries().to_vec();
+            if let Some(page) = page.as_f64() {
+                fields.push((key("pageno"), LuaValue::Number(page)));
+            }
+
+            let note = bookmarks
+                .iter()
+                .find(|bookmark| {
+                    is_highlighted(bookmark)
+                        && bookmark.get("datetime") == highlight.get("datetime")
+                })
+                .and_then(|bookmark| legacy_note(bookmark));
+            if let Some(note) = note {
+                fields.push((key("note"), LuaValue::String(note.to_string())));
+            }
+
+            annotations.push(LuaValue::Table(fields));
+        }
+    }
+
+    for bookmark in bookmarks
+        .into_iter()
+        .filter(|bookmark| !is_highlighted(bookmark))
+    {
+        let mut fields = bookmark.entries().to_vec();
+        // Page bookmarks have no highlighted position, see `read_metadata_file`
+        fields.retain(|(field_key, _)| field_key.as_str() != Some("pos0"));
+        annotations.push(LuaValue::Table(fields));
+    }
+
+    annotations
+}
+
+/// Returns the note written on a bookmark of the legacy layout, whose text defaults to a
+/// description like `Page 12 Don't stop. @ 2023-02-14 20:55:02` until the user edits it
+fn legacy_note(bookmark: &LuaValue) -> Option<&str> {
+    let text = bookmark.get("text")?.as_str()?.trim();
+    let datetime = bookmark.get("datetime")?.as_str()?;
+    let is_default = text.starts_with("Page ") && text.ends_with(&format!("@ {datetime}"));
+
+    (!text.is_empty() && !is_default).then_some(text)
+}
+
+/// Returns a non-empty document property, from the book metadata or the reading statistics
+fn document_property(metadata: &LuaValue, key: &str) -> Option<String> {
+    ["doc_props", "stats"]
+        .iter()
+        .filter_map(|table| metadata.get(table)?.get(key)?.as_str())
+        .map(str::trim)
+        .find(|property| !property.is_empty())
+        .map(str::to_string)
+}
+
+/// Returns the page number of an annotation, given by `pageno` for reflowable documents
+/// and by `page` for fixed layout ones like PDFs
+fn annotation_page(annotation: &LuaValue) -> Option<usize> {
+    ["pageno", "page"]
+        .iter()
+        .find_map(|key| annotation.get(key)?.as_f64())
+        .map(|page| page as usize)
+}
+
+/// Parses a KOReader date, saved in local time
+/// * Example: `2023-02-13 00:29:40`
+fn parse_koreader_date(date: &str) -> Result<DateTime<Local>> {
+    let naive = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+        .with_context(|| format!("Invalid KOReader date {date:?}"))?;
+
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .with_context(|| format!("Invalid local date {date:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::tests::local_dates;
+
+    #[test]
+    fn test_find_metadata_files() {
+        let mut metadata_files = Vec::new();
+        find_metadata_files(Path::new("tests/data/koreader"), &mut metadata_files)
+            .expect("Could not scan directory");
+
+        // Backups like `metadata.epub.lua.old` are skipped
+        assert_eq!(
+            metadata_files,
+            vec![
+                PathBuf::from("tests/data/koreader/Shoe Dog.sdr/metadata.epub.lua"),
+                PathBuf::from("tests/data/koreader/essays/Meditations.sdr/metadata.pdf.lua"),
+                PathBuf::from("tests/data/koreader/legacy/Trillions.sdr/metadata.epub.lua"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_clips() {
+        let clips = KoreaderDirectory::new(Path::new("tests/data/koreader"))
+            .read_clips()
+            .expect("Could not read KOReader directory");
+
+        // The legacy layout of Trillions is read too, skipping its highlight without datetime
+        insta::assert_yaml_snapshot!(local_dates(&clips));
+    }
+}
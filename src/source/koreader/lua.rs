@@ -0,0 +1,315 @@
+use anyhow::{anyhow, Result};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, take_while, take_while1},
+    character::complete::{char, multispace1, not_line_ending, one_of},
+    combinator::{all_consuming, map, opt, recognize, value},
+    error::{Error, ErrorKind},
+    multi::{many0, separated_list0},
+    number::complete::double,
+    sequence::{delimited, pair, separated_pair, terminated, tuple},
+    IResult,
+};
+
+/// Value of a Lua table literal, as written by KOReader
+///
+/// Only literals are supported, the file is parsed and never executed
+#[derive(Debug, Clone, PartialEq)]
+pub enum LuaValue {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(String),
+    /// Key/value pairs in file order, positional values getting their 1-based index as key
+    Table(Vec<(LuaValue, LuaValue)>),
+}
+
+impl LuaValue {
+    /// Returns the value of a string key, if this is a table containing it
+    pub fn get(&self, key: &str) -> Option<&LuaValue> {
+        match self {
+            LuaValue::Table(fields) => fields
+                .iter()
+                .find(|(field_key, _)| field_key.as_str() == Some(key))
+                .map(|(_, field_value)| field_value),
+            _ => None,
+        }
+    }
+
+    /// Returns the key/value pairs of the table, in file order, or nothing if this is not a table
+    pub fn entries(&self) -> &[(LuaValue, LuaValue)] {
+        match self {
+            LuaValue::Table(fields) => fields,
+            _ => &[],
+        }
+    }
+
+    /// Returns the values of the table, in file order, or nothing if this is not a table
+    pub fn values(&self) -> Vec<&LuaValue> {
+        match self {
+            LuaValue::Table(fields) => fields.iter().map(|(_, value)| value).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            LuaValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            LuaValue::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a Lua file made of a single table literal, optionally preceded by `return`
+/// # Variables
+/// * `input` - The file content
+///   * Example: `-- comment\nreturn { ["title"] = "Shoe Dog", [1] = true }`
+/// # Returns
+/// * `Result<LuaValue>` - The parsed value
+/// # Errors
+/// Raise if the input is not a single Lua literal
+pub fn parse_lua(input: &str) -> Result<LuaValue> {
+    let (_, value) = all_consuming(delimited(
+        pair(lua_space, opt(pair(tag("return"), lua_space))),
+        lua_value,
+        lua_space,
+    ))(input)
+    .map_err(|err| anyhow!("Invalid Lua table: {}", err.map_input(truncate)))?;
+
+    Ok(value)
+}
+
+/// Shortens the remaining input shown in parsing errors
+fn truncate(input: &str) -> String {
+    input.chars().take(40).collect()
+}
+
+/// Skips whitespace and `--` comments
+fn lua_space(input: &str) -> IResult<&str, ()> {
+    value(
+        (),
+        many0(alt((
+            value((), multispace1),
+            value((), pair(tag("--"), not_line_ending)),
+        ))),
+    )(input)
+}
+
+fn lua_value(input: &str) -> IResult<&str, LuaValue> {
+    alt((
+        value(LuaValue::Nil, terminated(tag("nil"), identifier_end)),
+        value(
+            LuaValue::Boolean(true),
+            terminated(tag("true"), identifier_end),
+        ),
+        value(
+            LuaValue::Boolean(false),
+            terminated(tag("false"), identifier_end),
+        ),
+        map(lua_string, LuaValue::String),
+        map(double, LuaValue::Number),
+        lua_table,
+    ))(input)
+}
+
+/// Makes sure a keyword is not the start of a longer identifier
+fn identifier_end(input: &str) -> IResult<&str, ()> {
+    match input.chars().next() {
+        Some(c) if c.is_alphanumeric() || c == '_' => {
+            Err(nom::Err::Error(Error::new(input, ErrorKind::Verify)))
+        }
+        _ => Ok((input, ())),
+    }
+}
+
+fn lua_identifier(input: &str) -> IResult<&str, &str> {
+    recognize(pair(
+        take_while1(|c: char| c.is_ascii_alphabetic() || c == '_'),
+        take_while(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+    ))(input)
+}
+
+/// Parses a table, like `{ ["key"] = "value", key = 1, "positional" }`
+fn lua_table(input: &str) -> IResult<&str, LuaValue> {
+    let field_separator = tuple((lua_space, one_of(",;"), lua_space));
+
+    let (input, fields) = delimited(
+        pair(char('{'), lua_space),
+        terminated(
+            separated_list0(field_separator, lua_field),
+            opt(tuple((lua_space, one_of(",;")))),
+        ),
+        pair(lua_space, char('}')),
+    )(input)?;
+
+    // Positional values are numbered from 1, like Lua does
+    let mut position = 0.0;
+    let fields = fields
+        .into_iter()
+        .map(|(key, field_value)| {
+            let key = key.unwrap_or_else(|| {
+                position += 1.0;
+                LuaValue::Number(position)
+            });
+            (key, field_value)
+        })
+        .collect();
+
+    Ok((input, LuaValue::Table(fields)))
+}
+
+/// Parses a table field, with its key if it is not positional
+fn lua_field(input: &str) -> IResult<&str, (Option<LuaValue>, LuaValue)> {
+    alt((
+        map(
+            separated_pair(
+                delimited(
+                    pair(char('['), lua_space),
+                    lua_value,
+                    pair(lua_space, char(']')),
+                ),
+                lua_equals,
+                lua_value,
+            ),
+            |(key, field_value)| (Some(key), field_value),
+        ),
+        map(
+            separated_pair(lua_identifier, lua_equals, lua_value),
+            |(key, field_value)| (Some(LuaValue::String(key.to_string())), field_value),
+        ),
+        map(lua_value, |field_value| (None, field_value)),
+    ))(input)
+}
+
+fn lua_equals(input: &str) -> IResult<&str, ()> {
+    value((), tuple((lua_space, char('='), lua_space)))(input)
+}
+
+/// Parses a quoted string and its escape sequences, as written by `string.format("%q")`
+fn lua_string(input: &str) -> IResult<&str, String> {
+    let error = |input| nom::Err::Error(Error::new(input, ErrorKind::Escaped));
+
+    let quote = match input.as_bytes().first() {
+        Some(quote @ (b'"' | b'\'')) => *quote,
+        _ => return Err(error(input)),
+    };
+
+    // Working on bytes is safe as UTF-8 continuation bytes never match ASCII characters
+    let bytes = input.as_bytes();
+    let mut string = Vec::new();
+    let mut idx = 1;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            byte if byte == quote => {
+                let string = String::from_utf8_lossy(&string).into_owned();
+                return Ok((&input[idx + 1..], string));
+            }
+            b'\n' => return Err(error(input)),
+            b'\\' => {
+                idx += 1;
+                let escaped = match bytes.get(idx) {
+                    Some(b'n' | b'\n') => b'\n',
+                    Some(b't') => b'\t',
+                    Some(b'r') => b'\r',
+                    Some(b'a') => 0x07,
+                    Some(b'b') => 0x08,
+                    Some(b'f') => 0x0c,
+                    Some(b'v') => 0x0b,
+                    Some(byte @ (b'\\' | b'"' | b'\'')) => *byte,
+                    // Decimal escapes have up to 3 digits, like `\0` or `\226`
+                    Some(b'0'..=b'9') => {
+                        let digits = bytes[idx..]
+                            .iter()
+                            .take(3)
+                            .take_while(|byte| byte.is_ascii_digit())
+                            .count();
+                        let code: u32 =
+                            input[idx..idx + digits].parse().map_err(|_| error(input))?;
+                        idx += digits - 1;
+                        u8::try_from(code).map_err(|_| error(input))?
+                    }
+                    _ => return Err(error(input)),
+                };
+                string.push(escaped);
+            }
+            byte => string.push(byte),
+        }
+        idx += 1;
+    }
+
+    Err(error(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lua_string() {
+        assert_eq!(
+            lua_string(
+                r#""Say \"hi\"\
+to \226\128\148 them" rest"#
+            ),
+            Ok((" rest", "Say \"hi\"\nto — them".to_string()))
+        );
+        assert_eq!(lua_string("'single'"), Ok(("", "single".to_string())));
+        assert!(lua_string("\"unfinished").is_err());
+    }
+
+    #[test]
+    fn test_parse_lua() {
+        let input = r#"-- we can read Lua syntax here!
+return {
+    ["doc_props"] = {
+        ["title"] = "Shoe Dog",
+    },
+    ["percent_finished"] = 0.25,
+    [2] = -1e3,
+    summary = { status = "reading", },
+    ["list"] = { "a"; "b", nil, true },
+}
+"#;
+        let parsed = parse_lua(input).expect("Could not parse Lua");
+
+        assert_eq!(
+            parsed.get("doc_props").and_then(|props| props.get("title")),
+            Some(&LuaValue::String("Shoe Dog".to_string()))
+        );
+        assert_eq!(
+            parsed.get("percent_finished").and_then(LuaValue::as_f64),
+            Some(0.25)
+        );
+        assert_eq!(
+            parsed
+                .get("summary")
+                .and_then(|summary| summary.get("status"))
+                .and_then(LuaValue::as_str),
+            Some("reading")
+        );
+        assert_eq!(
+            parsed.get("list").unwrap().values(),
+            vec![
+                &LuaValue::String("a".to_string()),
+                &LuaValue::String("b".to_string()),
+                &LuaValue::Nil,
+                &LuaValue::Boolean(true)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_lua_code_is_rejected() {
+        // Only literals are accepted, anything else is an error rather than being run
+        assert!(parse_lua("return { title = os.execute(\"rm -rf /\") }").is_err());
+        assert!(parse_lua("return { title = \"a\" .. \"b\" }").is_err());
+        assert!(parse_lua("return { } { }").is_err());
+    }
+}
@@ -0,0 +1,330 @@
+use crate::clippings::{parse_authors, Clip, ClipKind};
+use crate::source::ClippingSource;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Kindle notebook HTML exports, as emailed by the Kindle apps
+///
+/// Notebooks have the chapters and highlight colors the clippings file lacks, but no dates, and
+/// name books like the store. Their clips only enrich the clips of the other sources, see
+/// `group_enriched_clips`, as the ones matching none would be uploaded again on every run.
+pub struct KindleNotebook {
+    path: PathBuf,
+}
+
+impl KindleNotebook {
+    /// # Arguments
+    ///
+    /// * `path` - A notebook `.html` file, or a directory containing them
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl ClippingSource for KindleNotebook {
+    fn name(&self) -> &str {
+        "Kindle notebooks"
+    }
+
+    fn enriches_only(&self) -> bool {
+        true
+    }
+
+    fn read_clips(&self) -> Result<Vec<Clip>> {
+        let files = if self.path.is_dir() {
+            let mut files: Vec<PathBuf> = fs::read_dir(&self.path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<_>>()?;
+            files.retain(|file| matches!(file.extension(), Some(extension) if extension == "html"));
+            files.sort();
+            files
+        } else {
+            vec![self.path.clone()]
+        };
+
+        let mut clips = Vec::new();
+        for file in files {
+            let html = fs::read_to_string(&file)
+                .with_context(|| format!("Notebook not found: {file:?}"))?;
+            let date: DateTime<Local> = fs::metadata(&file)?.modified()?.into();
+
+            clips.extend(
+                parse_notebook(&html, date)
+                    .with_context(|| format!("Invalid notebook {file:?}"))?,
+            );
+        }
+
+        Ok(clips)
+    }
+}
+
+/// Parses a notebook export into clips
+/// # Variables
+/// * `html` - The notebook HTML
+/// * `date` - The date given to the clips, as notebooks do not have any
+/// # Returns
+/// * `Result<Vec<Clip>>` - The clips, with their chapter and color
+/// # Errors
+/// Raise if the notebook has no book title
+pub fn parse_notebook(html: &str, date: DateTime<Local>) -> Result<Vec<Clip>> {
+    let mut book = None;
+    let mut author = String::new();
+    let mut chapter = None;
+    let mut clips: Vec<Clip> = Vec::new();
+
+    for (class, text) in notebook_elements(html) {
+        match class {
+            "bookTitle" => book = Some(text),
+            "authors" => author = text,
+            "sectionHeading" => chapter = Some(text),
+            "noteHeading" => {
+                let Some(book) = &book else {
+                    bail!("Highlight found before the book title");
+                };
+
+                let (kind, color, page, location) = parse_note_heading(&text);
+                clips.push(Clip {
+                    book: book.clone(),
+                    authors: parse_authors(&author),
+                    source_author: author.clone(),
+                    kind,
+                    content: String::new(),
+                    date,
+                    location: (location, location),
+                    page,
+                    chapter: chapter.clone(),
+                    progress: None,
+                    color,
+                    truncated: false,
+                    approximate_location: false,
+                });
+            }
+            // The text belongs to the heading before it
+            "noteText" => {
+                if let Some(clip) = clips.last_mut() {
+                    clip.content = text;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if book.is_none() {
+        bail!("No book title found");
+    }
+
+    Ok(clips)
+}
+
+/// Returns the class and text of the notebook elements, in document order
+///
+/// Notebooks are not valid HTML, note texts being closed by `</h3>` instead of `</div>`, so
+/// elements are found by class and end at the next block tag.
+fn notebook_elements(html: &str) -> Vec<(&str, String)> {
+    const CLASSES: [&str; 5] = [
+        "bookTitle",
+        "authors",
+        "sectionHeading",
+        "noteHeading",
+        "noteText",
+    ];
+    const BLOCK_TAGS: [&str; 7] = ["<div", "</div", "<h2", "</h2", "<h3", "</h3", "<hr"];
+
+    let mut elements = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find("class=") {
+        rest = &rest[start + "class=".len()..];
+
+        let Some(quote) = rest.chars().next().filter(|c| *c == '\'' || *c == '"') else {
+            continue;
+        };
+        let Some(class_end) = rest[1..].find(quote) else {
+            break;
+        };
+        let class = &rest[1..=class_end];
+
+        let Some(content_start) = rest.find('>') else {
+            break;
+        };
+        rest = &rest[content_start + 1..];
+
+        let Some(class) = CLASSES.iter().find(|known| **known == class) else {
+            continue;
+        };
+
+        let content_end = BLOCK_TAGS
+            .iter()
+            .filter_map(|tag| rest.find(tag))
+            .min()
+            .unwrap_or(rest.len());
+        elements.push((*class, html_text(&rest[..content_end])));
+        rest = &rest[content_end..];
+    }
+
+    elements
+}
+
+/// Parses a note heading
+/// * Example: `Highlight(yellow) - Page 58 · Location 877`
+/// # Returns
+/// * `(ClipKind, Option<String>, Option<usize>, usize)` - The clip kind, color, page and location
+fn parse_note_heading(heading: &str) -> (ClipKind, Option<String>, Option<usize>, usize) {
+    let lowercase = heading.to_lowercase();
+
+    let kind = if lowercase.starts_with("note") {
+        ClipKind::Note
+    } else if lowercase.starts_with("bookmark") {
+        ClipKind::Bookmark
+    } else {
+        ClipKind::Highlight
+    };
+
+    // The color is in parentheses, right after the kind
+    let color = lowercase
+        .split_once('(')
+        .filter(|(kind, _)| !kind.contains('-'))
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(color, _)| color.trim().to_string())
+        .filter(|color| !color.is_empty());
+
+    let page = number_after(&lowercase, "page ");
+    // Books without locations only have pages, used as locations then
+    let location = number_after(&lowercase, "location ")
+        .or(page)
+        .unwrap_or_default();
+
+    (kind, color, page, location)
+}
+
+/// Returns the number following a label, if it is a decimal number
+fn number_after(text: &str, label: &str) -> Option<usize> {
+    let (_, rest) = text.split_once(label)?;
+
+    rest.split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|number| number.parse().ok())
+}
+
+/// Returns the text of a HTML fragment, without tags, with entities decoded and whitespace collapsed
+pub(crate) fn html_text(fragment: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in fragment.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decodes the HTML entities notebooks use, like `&amp;`, `&middot;` or `&#8217;`
+pub(crate) fn decode_entities(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "middot" => Some('·'),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        });
+
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::{group_enriched_clips, parse_clip_list};
+    use crate::source::tests::local_dates;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_note_heading() {
+        assert_eq!(
+            parse_note_heading("Highlight(yellow) - Page 58 · Location 877"),
+            (
+                ClipKind::Highlight,
+                Some("yellow".to_string()),
+                Some(58),
+                877
+            )
+        );
+        assert_eq!(
+            parse_note_heading("Note - Page xii · Location 170"),
+            (ClipKind::Note, None, None, 170)
+        );
+        assert_eq!(
+            parse_note_heading("Highlight (Blue) - Page 12"),
+            (ClipKind::Highlight, Some("blue".to_string()), Some(12), 12)
+        );
+    }
+
+    #[test]
+    fn test_html_text() {
+        assert_eq!(
+            html_text("\n Only in the notebook &amp; not in <i>My Clippings</i>&#8217;s &#x2014; &bogus\n"),
+            "Only in the notebook & not in My Clippings’s — &bogus"
+        );
+    }
+
+    #[test]
+    fn test_merge_with_clippings() {
+        let html = fs::read_to_string("tests/data/notebook.html").expect("Test file not found");
+        let date = Local.with_ymd_and_hms(2023, 3, 1, 12, 0, 0).unwrap();
+        let notebook_clips = parse_notebook(&html, date).expect("Could not parse notebook");
+        assert_eq!(notebook_clips.len(), 4);
+
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let clips = parse_clip_list(&input);
+
+        // The notebook clips are merged into the Shoe Dog ones, which gain chapters and colors,
+        // and the clip only in the notebook is dropped
+        let books_clips = group_enriched_clips(clips, notebook_clips);
+        let shoe_dog = books_clips.last().unwrap();
+        assert_eq!(shoe_dog.book_name, "Shoe Dog");
+        insta::assert_yaml_snapshot!(local_dates(shoe_dog));
+    }
+}
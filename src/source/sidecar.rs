@@ -0,0 +1,441 @@
+mod krds;
+
+use crate::clippings::{is_same_clip, is_same_title, BookClips, Clip, ClipKind};
+use crate::source::asin::split_file_name;
+use crate::source::ClippingSource;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use krds::{find_objects, parse_krds, KrdsValue};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// MOBI and AZW3 positions are byte offsets, with a location every this many bytes
+const MOBI_BYTES_PER_LOCATION: usize = 150;
+
+/// KFX locations come from the location map of the book, so they are approximated from the
+/// positions, with a location about every this many positions, and never matched on, see
+/// `Clip::approximate_location`
+const KFX_POSITIONS_PER_LOCATION: usize = 110;
+
+/// Extensions of the sidecar files holding annotations: KRDS `.azw3r` and `.pds` for MOBI, AZW3
+/// and PDF books, and `.yjr` for KFX books
+const ANNOTATION_EXTENSIONS: [&str; 3] = ["azw3r", "pds", "yjr"];
+
+/// Kindle `documents` directory, whose `*.sdr` directories hold the annotations of each book
+///
+/// Sidecars are the current state of the annotations: they are not limited by the publisher
+/// clipping limit, and deleted or moved highlights are gone from them. They do not store the
+/// highlighted text or the author though, so their clips only enrich the clips of the clippings
+/// file, see `group_enriched_clips`. The annotations missing from the clippings file are listed
+/// by `compare_with_clippings`.
+pub struct KindleSidecars {
+    path: PathBuf,
+}
+
+impl KindleSidecars {
+    /// # Arguments
+    ///
+    /// * `path` - The Kindle `documents` directory, or any directory tree containing `*.sdr`
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl ClippingSource for KindleSidecars {
+    fn name(&self) -> &str {
+        "Kindle sidecars"
+    }
+
+    fn enriches_only(&self) -> bool {
+        true
+    }
+
+    fn read_clips(&self) -> Result<Vec<Clip>> {
+        let mut sidecar_files = Vec::new();
+        find_sidecar_files(&self.path, &mut sidecar_files)
+            .with_context(|| format!("Could not scan Kindle documents {:?}", self.path))?;
+
+        let mut clips = Vec::new();
+        for sidecar_file in sidecar_files {
+            match read_sidecar_file(&sidecar_file) {
+                Ok(file_clips) => clips.extend(file_clips),
+                Err(err) => eprintln!("Skipping {sidecar_file:?}: {err:#}"),
+            }
+        }
+
+        Ok(clips)
+    }
+}
+
+/// Comparison of the clips of a book read from the sidecars and from the clippings file
+#[derive(Debug, Serialize)]
+pub struct SidecarComparison {
+    pub book_name: String,
+    /// Number of sidecar clips found in the clippings file
+    pub matched: usize,
+    /// Sidecar clips missing from the clippings file, like the ones past the clipping limit
+    pub only_in_sidecars: Vec<Clip>,
+    /// Clippings missing from the sidecars, as they were deleted or moved on the Kindle
+    pub only_in_clippings: Vec<Clip>,
+}
+
+/// Compares the clips read from the sidecars with the clips of the clippings file
+/// # Variables
+/// * `sidecar_clips` - The clips read by `KindleSidecars`
+/// * `books_clips` - The clips found by `parse_clips`
+/// # Returns
+/// * `Vec<SidecarComparison>` - The comparison of every sidecar book, books without sidecars
+///   being left out
+pub fn compare_with_clippings(
+    sidecar_clips: &[Clip],
+    books_clips: &[BookClips],
+) -> Vec<SidecarComparison> {
+    let mut comparisons: Vec<SidecarComparison> = Vec::new();
+
+    for clip in sidecar_clips {
+        let book_clips = books_clips
+            .iter()
+            .find(|book| is_same_title(&book.book_name, &clip.book));
+
+        let book_name = book_clips.map_or(&clip.book, |book| &book.book_name);
+
+        let comparison = match comparisons
+            .iter()
+            .position(|comparison| comparison.book_name == *book_name)
+        {
+            Some(idx) => &mut comparisons[idx],
+            None => {
+                comparisons.push(SidecarComparison {
+                    book_name: book_name.clone(),
+                    matched: 0,
+                    only_in_sidecars: Vec::new(),
+                    only_in_clippings: book_clips
+                        .map(|book| book.clips.clone())
+                        .unwrap_or_default(),
+                });
+                comparisons.last_mut().unwrap()
+            }
+        };
+
+        match comparison
+            .only_in_clippings
+            .iter()
+            .position(|clipping| is_same_clip(clipping, clip))
+        {
+            Some(idx) => {
+                comparison.only_in_clippings.remove(idx);
+                comparison.matched += 1;
+            }
+            None => comparison.only_in_sidecars.push(clip.clone()),
+        }
+    }
+
+    comparisons
+}
+
+/// Collects the annotation files of the `*.sdr` directories, recursively and sorted by path
+fn find_sidecar_files(dir: &Path, sidecar_files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.path());
+
+    let is_sdr = matches!(dir.extension(), Some(extension) if extension == "sdr");
+
+    // Symbolic links are not followed, to avoid loops
+    for entry in entries {
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            find_sidecar_files(&path, sidecar_files)?;
+        } else if is_sdr
+            && file_type.is_file()
+            && matches!(path.extension(), Some(extension) if ANNOTATION_EXTENSIONS.iter().any(|known| extension == *known))
+        {
+            sidecar_files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the clips of a sidecar file
+///
+/// Annotations are `annotation.personal.*` objects, whose values are the start and end
+/// positions, the creation and modification dates, a template and, for notes, the text.
+/// # Errors
+/// Raise if the file cannot be read or parsed
+fn read_sidecar_file(path: &Path) -> Result<Vec<Clip>> {
+    let values = parse_krds(&fs::read(path)?)?;
+
+    // Sidecars do not store the title, which the book file and its directory are named after
+    let book = path
+        .parent()
+        .and_then(Path::file_name)
+        .map(|dir_name| sidecar_title(&dir_name.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut clips = Vec::new();
+    for (name, fields) in find_objects(&values, "annotation.personal.") {
+        let kind = match name {
+            "annotation.personal.highlight" => ClipKind::Highlight,
+            "annotation.personal.note" => ClipKind::Note,
+            "annotation.personal.bookmark" => ClipKind::Bookmark,
+            _ => continue,
+        };
+
+        let strings: Vec<&str> = fields.iter().filter_map(KrdsValue::as_str).collect();
+        let (Some(start), Some(end)) = (strings.first(), strings.get(1)) else {
+            bail!("Annotation without position");
+        };
+        let approximate_location = is_kfx_position(start);
+        let start =
+            position_location(start).with_context(|| format!("Invalid position {start:?}"))?;
+        // Bookmarks and notes may have no end
+        let end = position_location(end).unwrap_or(start).max(start);
+
+        let created = fields
+            .iter()
+            .find_map(KrdsValue::as_i64)
+            .context("Annotation without date")?;
+
+        // The text of a note follows its template
+        let content = match kind {
+            ClipKind::Note => strings.get(3).copied().unwrap_or_default().trim(),
+            _ => "",
+        };
+
+        clips.push(Clip {
+            book: book.clone(),
+            authors: Vec::new(),
+            source_author: String::new(),
+            kind,
+            content: content.to_string(),
+            date: sidecar_date(created),
+            location: (start, end),
+            page: None,
+            chapter: None,
+            progress: None,
+            color: None,
+            truncated: false,
+            approximate_location,
+        });
+    }
+
+    Ok(clips)
+}
+
+/// Returns the title of a book from its sidecar directory, named like the book file
+/// * Example: `Shoe Dog-asin_B00ZYVIVA0-type_EBOK-v_0.sdr` and `Shoe Dog_B00ZYVIVA0.sdr` give
+///   `Shoe Dog`
+fn sidecar_title(dir_name: &str) -> String {
+    split_file_name(dir_name.strip_suffix(".sdr").unwrap_or(dir_name)).0
+}
+
+/// Returns the location of a sidecar position
+/// * MOBI and AZW3 positions are byte offsets: `131400` gives 877
+/// * KFX positions are an encoded section followed by a position: `AbIHAAAAAAAA:96360` gives 877
+fn position_location(position: &str) -> Option<usize> {
+    let (offset, per_location) = match position.rsplit_once(':') {
+        Some((_, kfx_position)) => (kfx_position, KFX_POSITIONS_PER_LOCATION),
+        None => (position, MOBI_BYTES_PER_LOCATION),
+    };
+
+    offset
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .map(|offset| offset / per_location + 1)
+}
+
+/// Returns whether a sidecar position is a KFX one, whose location is approximated
+fn is_kfx_position(position: &str) -> bool {
+    position.contains(':')
+}
+
+/// Converts a sidecar date, in milliseconds since the Unix epoch, to a local date
+///
+/// Dates are kept to the second like in the clippings file, so the same clip has the same date
+fn sidecar_date(timestamp: i64) -> DateTime<Local> {
+    Utc.timestamp_opt(timestamp.div_euclid(1000), 0)
+        .single()
+        .unwrap_or_default()
+        .with_timezone(&Local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::krds::tests::{int, krds_file, long, object, utf};
+    use super::*;
+    use crate::clippings::{group_enriched_clips, parse_clip_list, parse_clips};
+    use crate::source::tests::{create_fixture_directory, local_dates};
+
+    /// Returns the milliseconds timestamp of a local date
+    fn timestamp(day: u32, hour: u32, minute: u32, second: u32) -> i64 {
+        Local
+            .with_ymd_and_hms(2023, 2, day, hour, minute, second)
+            .unwrap()
+            .timestamp_millis()
+            + 123
+    }
+
+    fn annotation(kind: &str, start: &str, end: &str, created: i64, note: &str) -> Vec<u8> {
+        let mut values = vec![utf(start), utf(end), long(created), long(created), utf("0")];
+        if kind == "note" {
+            values.push(utf(note));
+        }
+
+        object(&format!("annotation.personal.{kind}"), &values)
+    }
+
+    /// Builds a `documents` directory with the sidecars of a KRDS and a KFX book
+    fn create_fixture_sidecars() -> PathBuf {
+        let dir = create_fixture_directory("sidecar");
+
+        // The bookmark of the clippings file was deleted, and a highlight past the clipping
+        // limit was added
+        let shoe_dog = dir.join("Shoe Dog-asin_B00ZYVIVA0-type_EBOK-v_0.sdr");
+        fs::create_dir_all(&shoe_dog).expect("Could not create fixture directory");
+        let annotations = [
+            annotation(
+                "highlight",
+                "131400",
+                "131849",
+                timestamp(13, 0, 29, 40),
+                "",
+            ),
+            annotation(
+                "note",
+                "131849",
+                "131849",
+                timestamp(13, 0, 30, 12),
+                "Forgetting as a skill",
+            ),
+            annotation("highlight", "180000", "180300", timestamp(14, 9, 0, 0), ""),
+        ];
+        fs::write(
+            shoe_dog.join("Shoe Dog-asin_B00ZYVIVA0-type_EBOK-v_0.azw3r"),
+            krds_file(&[object(
+                "annotation.cache.object",
+                &[[int(3)].as_slice(), &annotations].concat(),
+            )]),
+        )
+        .unwrap();
+        // Reading settings, without annotations
+        fs::write(
+            shoe_dog.join("Shoe Dog-asin_B00ZYVIVA0-type_EBOK-v_0.azw3f"),
+            krds_file(&[object("font.prefs", &[utf("Bookerly")])]),
+        )
+        .unwrap();
+
+        let meditations = dir.join("Meditations_B0MEDITATE.sdr");
+        fs::create_dir_all(&meditations).unwrap();
+        fs::write(
+            meditations.join("Meditations_B0MEDITATE.yjr"),
+            krds_file(&[object(
+                "annotation.cache.object",
+                &[annotation(
+                    "highlight",
+                    "AbIHAAAAAAAA:7700",
+                    "AbIHAAAAAAAA:7790",
+                    timestamp(15, 12, 0, 0),
+                    "",
+                )],
+            )]),
+        )
+        .unwrap();
+
+        dir
+    }
+
+    fn read_fixture_clips() -> Vec<Clip> {
+        let dir = create_fixture_sidecars();
+        let clips = KindleSidecars::new(&dir)
+            .read_clips()
+            .expect("Could not read sidecars");
+        fs::remove_dir_all(&dir).ok();
+
+        clips
+    }
+
+    #[test]
+    fn test_sidecar_title() {
+        assert_eq!(
+            sidecar_title("Shoe Dog-asin_B00ZYVIVA0-type_EBOK-v_0.sdr"),
+            "Shoe Dog"
+        );
+        assert_eq!(sidecar_title("Shoe Dog_B00ZYVIVA0.sdr"), "Shoe Dog");
+        assert_eq!(sidecar_title("my_notes.sdr"), "my_notes");
+    }
+
+    #[test]
+    fn test_position_location() {
+        assert_eq!(position_location("131400"), Some(877));
+        assert_eq!(position_location("AbIHAAAAAAAA:96360"), Some(877));
+        assert_eq!(position_location("unknown"), None);
+    }
+
+    #[test]
+    fn test_read_clips() {
+        insta::assert_yaml_snapshot!(local_dates(&read_fixture_clips()));
+    }
+
+    #[test]
+    fn test_compare_with_clippings() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let comparisons = compare_with_clippings(&read_fixture_clips(), &parse_clips(&input));
+
+        // Books without clippings only have sidecar clips
+        assert_eq!(comparisons[0].book_name, "Meditations");
+        assert_eq!(comparisons[0].only_in_sidecars.len(), 1);
+
+        let shoe_dog = &comparisons[1];
+        assert_eq!(shoe_dog.book_name, "Shoe Dog");
+        assert_eq!(shoe_dog.matched, 2);
+        assert_eq!(shoe_dog.only_in_sidecars[0].location, (1201, 1203));
+        assert_eq!(shoe_dog.only_in_clippings[0].kind, ClipKind::Bookmark);
+    }
+
+    #[test]
+    fn test_merge_with_clippings() {
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let clips = parse_clip_list(&input);
+        let clips_count = clips.len();
+
+        // The sidecar clips are merged into the clippings ones, and the highlights missing from
+        // the clippings file, without text, are left out
+        let books_clips = group_enriched_clips(clips, read_fixture_clips());
+        assert_eq!(
+            books_clips
+                .iter()
+                .map(|book| book.clips.len())
+                .sum::<usize>(),
+            clips_count
+        );
+        assert!(!books_clips
+            .iter()
+            .any(|book| book.book_name == "Meditations" || book.authors.is_empty()));
+    }
+
+    #[test]
+    fn test_kfx_locations_are_approximate() {
+        let clips = read_fixture_clips();
+        let meditations = clips
+            .iter()
+            .find(|clip| clip.book == "Meditations")
+            .unwrap();
+        assert!(meditations.approximate_location);
+
+        // A clip at the same location but saved at another date is another clip
+        let mut other = meditations.clone();
+        other.date += chrono::Duration::seconds(1);
+        other.approximate_location = false;
+        assert!(!is_same_clip(&other, meditations));
+
+        other.date = meditations.date;
+        other.location.0 += 3;
+        assert!(is_same_clip(&other, meditations));
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{anyhow, Result};
+use nom::{
+    bytes::complete::tag,
+    combinator::{all_consuming, map},
+    error::{Error, ErrorKind},
+    multi::{length_data, many0},
+    number::complete::{be_f32, be_f64, be_i16, be_i32, be_i64, be_i8, be_u16, be_u8},
+    sequence::{pair, preceded, terminated},
+    IResult,
+};
+
+/// Signature starting every KRDS file
+const SIGNATURE: &[u8] = b"\x00\x00\x00\x00\x00\x1a\xb1\x26";
+
+/// Data type starting a named object, whose values end with `OBJECT_END`
+const OBJECT_BEGIN: i8 = -2;
+const OBJECT_END: &[u8] = &[0xff];
+
+/// Value of a KRDS (Kindle Reader Data Store) file, the format of the `.azw3r`, `.pds` and `.yjr`
+/// sidecar files
+///
+/// Every value is preceded by its data type, so files are parsed without knowing their objects
+#[derive(Debug, Clone, PartialEq)]
+pub enum KrdsValue {
+    Boolean(bool),
+    Int(i32),
+    Long(i64),
+    Utf(String),
+    Double(f64),
+    Short(i16),
+    Float(f32),
+    Byte(i8),
+    Char(u16),
+    /// Named object, like `annotation.personal.highlight`, with its values in file order
+    Object(String, Vec<KrdsValue>),
+}
+
+impl KrdsValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            KrdsValue::Utf(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            KrdsValue::Long(number) => Some(*number),
+            KrdsValue::Int(number) => Some(i64::from(*number)),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a KRDS file
+/// # Variables
+/// * `input` - The file content
+/// # Returns
+/// * `Result<Vec<KrdsValue>>` - The values following the signature, like the file version and
+///   the top level objects
+/// # Errors
+/// Raise if the input is not a KRDS file, or has an unknown data type
+pub fn parse_krds(input: &[u8]) -> Result<Vec<KrdsValue>> {
+    let (_, values) =
+        all_consuming(preceded(tag(SIGNATURE), many0(krds_value)))(input).map_err(|err| {
+            anyhow!(
+                "Invalid KRDS file: {}",
+                err.map_input(|rest: &[u8]| format!("byte {}", input.len() - rest.len()))
+            )
+        })?;
+
+    Ok(values)
+}
+
+/// Returns the objects whose name starts with `prefix`, searching nested objects too
+/// # Returns
+/// * `Vec<(&str, &[KrdsValue])>` - The name and values of the objects, in file order
+pub fn find_objects<'a>(values: &'a [KrdsValue], prefix: &str) -> Vec<(&'a str, &'a [KrdsValue])> {
+    let mut objects = Vec::new();
+
+    for value in values {
+        if let KrdsValue::Object(name, object_values) = value {
+            if name.starts_with(prefix) {
+                objects.push((name.as_str(), object_values.as_slice()));
+            }
+            objects.extend(find_objects(object_values, prefix));
+        }
+    }
+
+    objects
+}
+
+/// Parses a value, preceded by its data type
+fn krds_value(input: &[u8]) -> IResult<&[u8], KrdsValue> {
+    let (input, data_type) = be_i8(input)?;
+
+    match data_type {
+        0 => map(be_u8, |byte| KrdsValue::Boolean(byte != 0))(input),
+        1 => map(be_i32, KrdsValue::Int)(input),
+        2 => map(be_i64, KrdsValue::Long)(input),
+        3 => map(krds_utf, KrdsValue::Utf)(input),
+        4 => map(be_f64, KrdsValue::Double)(input),
+        5 => map(be_i16, KrdsValue::Short)(input),
+        6 => map(be_f32, KrdsValue::Float)(input),
+        7 => map(be_i8, KrdsValue::Byte)(input),
+        9 => map(be_u16, KrdsValue::Char)(input),
+        OBJECT_BEGIN => map(
+            pair(krds_utf, terminated(many0(krds_value), tag(OBJECT_END))),
+            |(name, values)| KrdsValue::Object(name, values),
+        )(input),
+        _ => Err(nom::Err::Error(Error::new(input, ErrorKind::Switch))),
+    }
+}
+
+/// Parses a string, which is a flag set for empty strings, or the length and UTF-8 bytes
+fn krds_utf(input: &[u8]) -> IResult<&[u8], String> {
+    let (input, is_empty) = be_u8(input)?;
+    if is_empty != 0 {
+        return Ok((input, String::new()));
+    }
+
+    map(length_data(be_u16), |bytes| {
+        String::from_utf8_lossy(bytes).into_owned()
+    })(input)
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Encodes the file, with its signature and version
+    pub(in crate::source::sidecar) fn krds_file(objects: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = SIGNATURE.to_vec();
+        file.extend(int(1));
+        file.extend(long(objects.len() as i64));
+        file.extend(objects.concat());
+        file
+    }
+
+    pub(in crate::source::sidecar) fn object(name: &str, values: &[Vec<u8>]) -> Vec<u8> {
+        let mut object = vec![OBJECT_BEGIN as u8];
+        object.extend(&utf(name)[1..]);
+        object.extend(values.concat());
+        object.extend(OBJECT_END);
+        object
+    }
+
+    pub(in crate::source::sidecar) fn int(number: i32) -> Vec<u8> {
+        [&[1], &number.to_be_bytes()[..]].concat()
+    }
+
+    pub(in crate::source::sidecar) fn long(number: i64) -> Vec<u8> {
+        [&[2], &number.to_be_bytes()[..]].concat()
+    }
+
+    pub(in crate::source::sidecar) fn utf(string: &str) -> Vec<u8> {
+        if string.is_empty() {
+            return vec![3, 1];
+        }
+
+        let length = u16::try_from(string.len()).unwrap();
+        [&[3, 0], &length.to_be_bytes()[..], string.as_bytes()].concat()
+    }
+
+    #[test]
+    fn test_parse_krds() {
+        let file = krds_file(&[
+            object("font.prefs", &[utf("Bookerly"), int(3), vec![0, 1]]),
+            object(
+                "annotation.cache.object",
+                &[
+                    int(1),
+                    int(1),
+                    object("annotation.personal.bookmark", &[utf("42"), utf("")]),
+                ],
+            ),
+        ]);
+        let values = parse_krds(&file).expect("Could not parse KRDS file");
+
+        assert_eq!(values[1], KrdsValue::Long(2));
+        assert_eq!(
+            values[2],
+            KrdsValue::Object(
+                "font.prefs".to_string(),
+                vec![
+                    KrdsValue::Utf("Bookerly".to_string()),
+                    KrdsValue::Int(3),
+                    KrdsValue::Boolean(true),
+                ]
+            )
+        );
+        assert_eq!(
+            find_objects(&values, "annotation.personal."),
+            vec![(
+                "annotation.personal.bookmark",
+                &[
+                    KrdsValue::Utf("42".to_string()),
+                    KrdsValue::Utf(String::new())
+                ][..]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_invalid_krds() {
+        assert!(parse_krds(b"not a KRDS file").is_err());
+
+        // Unknown data type, and object without end
+        let mut file = krds_file(&[]);
+        file.push(8);
+        assert!(parse_krds(&file).is_err());
+        assert!(parse_krds(&krds_file(&[vec![OBJECT_BEGIN as u8, 1]])).is_err());
+    }
+}
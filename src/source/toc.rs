@@ -0,0 +1,876 @@
+use crate::clippings::{matching_titles, BookClips};
+use crate::source::asin::{find_files, read_mobi_metadata, split_file_name};
+use crate::source::calibre::attribute;
+use crate::source::notebook::html_text;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Extensions of the book files whose table of contents can be read
+const TOC_EXTENSIONS: [&str; 4] = ["epub", "mobi", "azw", "azw3"];
+
+/// Bytes of book text per Kindle location
+const BYTES_PER_LOCATION: usize = 150;
+
+/// MOBI text compressions
+const NO_COMPRESSION: u16 = 1;
+const PALMDOC_COMPRESSION: u16 = 2;
+const HUFF_CDIC_COMPRESSION: u16 = 17480;
+
+/// MOBI text encoding of UTF-8 books, the others being Windows-1252
+const UTF8_ENCODING: u32 = 65001;
+
+/// Entry of the table of contents of a book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    /// Kindle location where the chapter starts
+    pub location: usize,
+    pub title: String,
+}
+
+/// DRM-free book files, whose table of contents give the chapter of the clips
+///
+/// Books are found in the Kindle `documents` directory or in a Calibre library, as EPUB, MOBI or
+/// AZW3 files. Kindle locations being 150 bytes of book text, chapters are located from the
+/// position of their start in the text, which is exact for MOBI books. EPUB books are not the
+/// text the Kindle counts locations in, so their chapters are located from the length of their
+/// text without markup, which is only an estimate: clips close to the start of a chapter can be
+/// attributed to its neighbour.
+#[derive(Debug, Clone, Default)]
+pub struct BookFiles {
+    /// Title and path of the books, in path order
+    files: Vec<(String, PathBuf)>,
+}
+
+impl BookFiles {
+    /// Scans directory trees for book files
+    ///
+    /// # Arguments
+    ///
+    /// * `dirs` - The directories to scan, like the Kindle `documents` directory
+    ///
+    /// # Errors
+    /// Raise if a directory cannot be read
+    pub fn scan(dirs: &[&Path]) -> Result<Self> {
+        let mut book_files = Vec::new();
+        for dir in dirs {
+            find_files(dir, &TOC_EXTENSIONS, &mut book_files)
+                .with_context(|| format!("Could not scan book files {dir:?}"))?;
+        }
+
+        let files = book_files
+            .into_iter()
+            .map(|path| {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let (file_title, _) = split_file_name(&stem);
+
+                // MOBI books have their title in their metadata, file names being truncated
+                let title = match read_mobi_metadata(&path) {
+                    Ok(Some((Some(title), _))) => title,
+                    _ => file_title,
+                };
+
+                (title, path)
+            })
+            .collect();
+
+        Ok(Self { files })
+    }
+
+    /// Sets the chapter of the clips without one, from the table of contents of their book
+    ///
+    /// Book files that cannot be read, like DRM protected ones, are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `books_clips` - The books whose clips get their chapter
+    ///
+    /// # Returns
+    /// The number of clips that got a chapter
+    pub fn attribute_chapters(&self, books_clips: &mut [BookClips]) -> usize {
+        let mut attributed = 0;
+
+        for book in books_clips.iter_mut() {
+            if book.clips.iter().all(|clip| clip.chapter.is_some()) {
+                continue;
+            }
+
+            let toc = matching_titles(&self.files, &book.book_name)
+                .into_iter()
+                .find_map(|(_, path)| match read_toc(path) {
+                    Ok(toc) if !toc.is_empty() => Some(toc),
+                    Ok(_) => None,
+                    Err(err) => {
+                        eprintln!("Could not read the table of contents of {path:?}: {err:#}");
+                        None
+                    }
+                });
+            let Some(toc) = toc else {
+                continue;
+            };
+
+            for clip in book.clips.iter_mut().filter(|clip| clip.chapter.is_none()) {
+                clip.chapter = chapter_at(&toc, clip.location.0).map(str::to_string);
+                if clip.chapter.is_some() {
+                    attributed += 1;
+                }
+            }
+        }
+
+        attributed
+    }
+}
+
+/// Reads the table of contents of an EPUB, MOBI or AZW3 book
+/// # Returns
+/// * `Result<Vec<TocEntry>>` - The entries sorted by location, nested ones included
+/// # Errors
+/// Raise if the file cannot be read, is DRM protected, or is not a book
+pub fn read_toc(path: &Path) -> Result<Vec<TocEntry>> {
+    let mut toc = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("epub") => read_epub_toc(fs::File::open(path)?)?,
+        _ => parse_mobi_toc(&fs::read(path)?)?,
+    };
+    toc.sort_by_key(|entry| entry.location);
+
+    Ok(toc)
+}
+
+/// Returns the title of the chapter a location is in, which is the last one starting before
+fn chapter_at(toc: &[TocEntry], location: usize) -> Option<&str> {
+    toc.iter()
+        .take_while(|entry| entry.location <= location)
+        .last()
+        .map(|entry| entry.title.as_str())
+}
+
+/// Document of the EPUB manifest
+struct ManifestItem {
+    id: String,
+    /// Path in the archive
+    path: String,
+    media_type: String,
+    properties: String,
+}
+
+/// Reads the table of contents of an EPUB book, from its EPUB 3 navigation document or its
+/// EPUB 2 NCX file
+fn read_epub_toc<R: Read + Seek>(reader: R) -> Result<Vec<TocEntry>> {
+    let mut epub = ZipArchive::new(reader).context("Invalid EPUB file")?;
+    if epub.by_name("META-INF/rights.xml").is_ok() {
+        bail!("The book is DRM protected");
+    }
+
+    let container = read_entry(&mut epub, "META-INF/container.xml")?;
+    let opf_path = start_tags(&container, "rootfile")
+        .into_iter()
+        .find_map(|attributes| attribute(attributes, "full-path"))
+        .context("No package document")?;
+    let opf = read_entry(&mut epub, &opf_path)?;
+
+    let items: Vec<ManifestItem> = start_tags(&opf, "item")
+        .into_iter()
+        .filter_map(|attributes| {
+            Some(ManifestItem {
+                id: attribute(attributes, "id")?,
+                path: join_path(parent_dir(&opf_path), &attribute(attributes, "href")?),
+                media_type: attribute(attributes, "media-type").unwrap_or_default(),
+                properties: attribute(attributes, "properties").unwrap_or_default(),
+            })
+        })
+        .collect();
+    let item = |id: &str| items.iter().find(|item| item.id == id);
+
+    // Path, start in the book text and content of the documents, in reading order
+    let mut spine = Vec::new();
+    let mut start = 0;
+    for idref in start_tags(&opf, "itemref")
+        .into_iter()
+        .filter_map(|attributes| attribute(attributes, "idref"))
+    {
+        if let Some(item) = item(&idref) {
+            let content = read_entry(&mut epub, &item.path)?;
+            let length = text_length(&content);
+            spine.push((item.path.as_str(), start, content));
+            start += length;
+        }
+    }
+
+    let nav = items.iter().find(|item| {
+        item.properties
+            .split_whitespace()
+            .any(|property| property == "nav")
+    });
+    let ncx = start_tags(&opf, "spine")
+        .into_iter()
+        .find_map(|attributes| attribute(attributes, "toc"))
+        .and_then(|id| item(&id))
+        .or_else(|| {
+            items
+                .iter()
+                .find(|item| item.media_type == "application/x-dtbncx+xml")
+        });
+
+    let (toc_path, links) = match (nav, ncx) {
+        (Some(nav), _) => (&nav.path, nav_links(&read_entry(&mut epub, &nav.path)?)),
+        (None, Some(ncx)) => (&ncx.path, ncx_links(&read_entry(&mut epub, &ncx.path)?)),
+        (None, None) => bail!("No table of contents"),
+    };
+
+    let toc = links
+        .into_iter()
+        .filter_map(|(href, title)| {
+            let (file, fragment) = match href.split_once('#') {
+                Some((file, fragment)) => (file, Some(fragment)),
+                None => (href.as_str(), None),
+            };
+            let file = join_path(parent_dir(toc_path), file);
+
+            let (_, start, content) = spine.iter().find(|(path, _, _)| *path == file)?;
+            let position = fragment
+                .and_then(|fragment| find_id(content, fragment))
+                .map_or(0, |position| text_length(&content[..position]));
+
+            Some(TocEntry {
+                location: (start + position) / BYTES_PER_LOCATION + 1,
+                title,
+            })
+        })
+        .collect();
+
+    Ok(toc)
+}
+
+/// Returns the length of the text of a document, or of its start, without markup nor `head`
+fn text_length(document: &str) -> usize {
+    let body = document
+        .find("<body")
+        .map_or(document, |start| &document[start..]);
+
+    html_text(body).len()
+}
+
+/// Returns the content of an archive file, as text
+fn read_entry<R: Read + Seek>(epub: &mut ZipArchive<R>, name: &str) -> Result<String> {
+    let mut content = Vec::new();
+    epub.by_name(name)
+        .with_context(|| format!("Missing EPUB file {name:?}"))?
+        .read_to_end(&mut content)?;
+
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// Returns the links of the `toc` navigation of an EPUB 3 navigation document
+/// # Returns
+/// * `Vec<(String, String)>` - The target and text of the links
+fn nav_links(nav: &str) -> Vec<(String, String)> {
+    let Some(toc) = nav.split("<nav").skip(1).find(|section| {
+        let attributes = section.split('>').next().unwrap_or_default();
+        matches!(attribute(attributes, "epub:type"), Some(nav_type) if nav_type.split_whitespace().any(|nav_type| nav_type == "toc"))
+    }) else {
+        return Vec::new();
+    };
+    let toc = toc.split("</nav>").next().unwrap_or_default();
+
+    toc.split("<a")
+        .skip(1)
+        .filter_map(|link| {
+            let (attributes, rest) = link.split_once('>')?;
+            // Skips other tags starting with `a`, like `<aside>`
+            if !attributes.starts_with(char::is_whitespace) {
+                return None;
+            }
+            let href = attribute(attributes, "href")?;
+            let title = html_text(rest.split("</a>").next().unwrap_or_default());
+            (!title.is_empty()).then_some((href, title))
+        })
+        .collect()
+}
+
+/// Returns the navigation points of an EPUB 2 NCX file
+/// # Returns
+/// * `Vec<(String, String)>` - The target and label of the navigation points
+fn ncx_links(ncx: &str) -> Vec<(String, String)> {
+    // Nested navigation points follow the label and target of their parent
+    ncx.split("<navPoint")
+        .skip(1)
+        .filter_map(|nav_point| {
+            let (_, label) = nav_point.split_once("<text>")?;
+            let title = html_text(label.split("</text>").next().unwrap_or_default());
+            let src = start_tags(nav_point, "content")
+                .into_iter()
+                .find_map(|attributes| attribute(attributes, "src"))?;
+            (!title.is_empty()).then_some((src, title))
+        })
+        .collect()
+}
+
+/// Returns the attributes of the start tags named `name`
+fn start_tags<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    xml.split(&format!("<{name}"))
+        .skip(1)
+        .filter(|tag| tag.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/'))
+        .map(|tag| tag.split('>').next().unwrap_or_default())
+        .collect()
+}
+
+/// Returns the position of the tag with the `id` in a document
+fn find_id(content: &str, id: &str) -> Option<usize> {
+    let id = decode_percent(id);
+    [format!("id=\"{id}\""), format!("id='{id}'")]
+        .iter()
+        .filter_map(|pattern| content.find(pattern.as_str()))
+        .min()
+        .map(|position| content[..position].rfind('<').unwrap_or(position))
+}
+
+/// Returns the directory of an archive path
+fn parent_dir(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Resolves a link relative to a directory of the archive
+/// * Example: `Text` and `../Images/cover.jpg` give `Images/cover.jpg`
+fn join_path(dir: &str, href: &str) -> String {
+    let mut parts: Vec<&str> = dir.split('/').filter(|part| !part.is_empty()).collect();
+    let href = decode_percent(href);
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+/// Decodes the percent encoded characters of a link, like `%20`
+fn decode_percent(link: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = link.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Reads the table of contents of a MOBI book, which is a HTML page of `filepos` links found
+/// with the `toc` reference of the guide
+///
+/// KF8 only AZW3 books have no such page, so their table of contents is empty.
+fn parse_mobi_toc(file: &[u8]) -> Result<Vec<TocEntry>> {
+    let (text, is_utf8) = mobi_text(file)?;
+
+    // Markup is ASCII, so replacing the other bytes keeps the positions of the text
+    let markup: String = text
+        .iter()
+        .map(|byte| if byte.is_ascii() { *byte as char } else { '?' })
+        .collect();
+
+    let Some(toc_start) = start_tags(&markup, "reference")
+        .into_iter()
+        .filter(|attributes| {
+            matches!(attribute(attributes, "type"), Some(reference_type) if reference_type.eq_ignore_ascii_case("toc"))
+        })
+        .find_map(filepos)
+        .filter(|toc_start| *toc_start < markup.len())
+    else {
+        return Ok(Vec::new());
+    };
+    let toc_end = markup[toc_start..]
+        .find("<mbp:pagebreak")
+        .map_or(markup.len(), |end| toc_start + end);
+
+    let mut toc = Vec::new();
+    for (link_start, _) in markup[toc_start..toc_end].match_indices("<a") {
+        let link = &markup[toc_start + link_start + 2..toc_end];
+        let Some(attributes) = link
+            .split_once('>')
+            .map(|(attributes, _)| attributes)
+            .filter(|attributes| attributes.starts_with(char::is_whitespace))
+        else {
+            continue;
+        };
+        let Some(target) = filepos(attributes) else {
+            continue;
+        };
+
+        // Positions of the link text in the book text
+        let title_start = toc_start + link_start + 2 + attributes.len() + 1;
+        let title_end = markup[title_start..toc_end]
+            .find("</a>")
+            .map_or(toc_end, |end| title_start + end);
+        let title_bytes = &text[title_start..title_end];
+        let title = if is_utf8 {
+            String::from_utf8_lossy(title_bytes).into_owned()
+        } else {
+            // Windows-1252 is close enough to Latin-1 for titles
+            title_bytes.iter().map(|byte| *byte as char).collect()
+        };
+
+        let title = html_text(&title);
+        if !title.is_empty() {
+            toc.push(TocEntry {
+                location: target / BYTES_PER_LOCATION + 1,
+                title,
+            });
+        }
+    }
+
+    Ok(toc)
+}
+
+/// Returns the `filepos` attribute of a MOBI tag, which is an unquoted offset in the book text
+fn filepos(attributes: &str) -> Option<usize> {
+    let (_, value) = attributes.split_once("filepos=")?;
+    value
+        .trim_start_matches(['"', '\''])
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|number| number.parse().ok())
+}
+
+/// Returns the uncompressed text of a MOBI book, and whether it is encoded in UTF-8
+fn mobi_text(file: &[u8]) -> Result<(Vec<u8>, bool)> {
+    let u16_at = |bytes: &[u8], offset: usize| {
+        bytes
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()))
+            .context("Truncated MOBI file")
+    };
+    let u32_at = |bytes: &[u8], offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+            .context("Truncated MOBI file")
+    };
+
+    if !matches!(file.get(60..68), Some(b"BOOKMOBI" | b"TEXtREAd")) {
+        bail!("Not a MOBI book");
+    }
+
+    let record_count = usize::from(u16_at(file, 76)?);
+    let record = |idx: usize| -> Result<&[u8]> {
+        let start = u32_at(file, 78 + idx * 8)? as usize;
+        let end = if idx + 1 < record_count {
+            u32_at(file, 78 + (idx + 1) * 8)? as usize
+        } else {
+            file.len()
+        };
+        file.get(start..end).context("Truncated MOBI file")
+    };
+
+    // The first record has the PalmDOC header, followed by the MOBI header
+    let header = record(0)?;
+    let compression = u16_at(header, 0)?;
+    let text_length = u32_at(header, 4)? as usize;
+    let text_records = usize::from(u16_at(header, 8)?);
+    if u16_at(header, 12)? != 0 {
+        bail!("The book is DRM protected");
+    }
+
+    let (is_utf8, extra_flags) = if header.get(16..20) == Some(b"MOBI") {
+        let header_length = u32_at(header, 20)?;
+        (
+            u32_at(header, 28)? == UTF8_ENCODING,
+            if header_length >= 0xE4 {
+                u16_at(header, 0xF2)?
+            } else {
+                0
+            },
+        )
+    } else {
+        (false, 0)
+    };
+
+    let mut text = Vec::new();
+    for idx in 1..=text_records.min(record_count.saturating_sub(1)) {
+        let data = record(idx)?;
+        let data = &data[..data.len() - trailing_size(data, extra_flags)];
+
+        match compression {
+            NO_COMPRESSION => text.extend(data),
+            PALMDOC_COMPRESSION => text.extend(palmdoc_decompress(data)),
+            HUFF_CDIC_COMPRESSION => bail!("HUFF/CDIC compressed books are not supported"),
+            compression => bail!("Unknown MOBI compression {compression}"),
+        }
+    }
+    text.truncate(text_length);
+
+    Ok((text, is_utf8))
+}
+
+/// Returns the size of the trailing entries of a text record, set by the extra flags
+fn trailing_size(record: &[u8], extra_flags: u16) -> usize {
+    let mut size = 0;
+
+    for bit in 1..16 {
+        if extra_flags & (1 << bit) != 0 {
+            // Sizes are written backwards, with the high bit starting them
+            let end = record.len().saturating_sub(size);
+            size += record[end.saturating_sub(4)..end]
+                .iter()
+                .fold(0, |entry_size, byte| {
+                    let bits = usize::from(byte & 0x7F);
+                    if byte & 0x80 != 0 {
+                        bits
+                    } else {
+                        (entry_size << 7) | bits
+                    }
+                });
+        }
+    }
+
+    // Multibyte characters overlapping the next record
+    if extra_flags & 1 != 0 {
+        if let Some(byte) = record.len().checked_sub(size + 1).map(|idx| record[idx]) {
+            size += usize::from(byte & 0x03) + 1;
+        }
+    }
+
+    size.min(record.len())
+}
+
+/// Decompresses a PalmDOC text record, whose LZ77 compression also packs spaces with letters
+fn palmdoc_decompress(data: &[u8]) -> Vec<u8> {
+    let mut text = Vec::with_capacity(data.len() * 2);
+    let mut idx = 0;
+
+    while idx < data.len() {
+        let byte = data[idx];
+        idx += 1;
+
+        match byte {
+            // Literal bytes
+            0x01..=0x08 => {
+                let end = (idx + usize::from(byte)).min(data.len());
+                text.extend(&data[idx..end]);
+                idx = end;
+            }
+            // Distance and length of a repeated sequence
+            0x80..=0xBF => {
+                let Some(next) = data.get(idx) else {
+                    break;
+                };
+                idx += 1;
+
+                let pair = u16::from_be_bytes([byte, *next]);
+                let distance = usize::from((pair >> 3) & 0x07FF);
+                let length = usize::from(pair & 0x07) + 3;
+                if distance == 0 || distance > text.len() {
+                    break;
+                }
+                for _ in 0..length {
+                    text.push(text[text.len() - distance]);
+                }
+            }
+            // Space followed by a letter
+            0xC0..=0xFF => {
+                text.push(b' ');
+                text.push(byte ^ 0x80);
+            }
+            _ => text.push(byte),
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clippings::parse_clips;
+    use crate::source::tests::create_fixture_directory;
+    use std::io::{Cursor, Write};
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    /// Encodes an EPUB archive with the given files
+    fn epub_file(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut epub = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            epub.start_file(*name, FileOptions::default()).unwrap();
+            epub.write_all(content.as_bytes()).unwrap();
+        }
+        epub.finish().unwrap().into_inner()
+    }
+
+    const CONTAINER: &str = r#"<container><rootfiles>
+        <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+    </rootfiles></container>"#;
+
+    /// Encodes an uncompressed MOBI file with the given text, split in records of 4096 bytes
+    fn mobi_file(text: &str) -> Vec<u8> {
+        let records: Vec<&[u8]> = text.as_bytes().chunks(4096).collect();
+
+        let mut header = vec![0; 16 + 232];
+        header[0..2].copy_from_slice(&NO_COMPRESSION.to_be_bytes());
+        header[4..8].copy_from_slice(&(text.len() as u32).to_be_bytes());
+        header[8..10].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        header[10..12].copy_from_slice(&4096_u16.to_be_bytes());
+        header[16..20].copy_from_slice(b"MOBI");
+        header[20..24].copy_from_slice(&232_u32.to_be_bytes());
+        header[28..32].copy_from_slice(&UTF8_ENCODING.to_be_bytes());
+
+        let mut file = vec![0; 78];
+        file[60..68].copy_from_slice(b"BOOKMOBI");
+        file[76..78].copy_from_slice(&(records.len() as u16 + 1).to_be_bytes());
+
+        let mut offset = 78 + (records.len() + 1) * 8;
+        let mut data: Vec<u8> = Vec::new();
+        for record in std::iter::once(header.as_slice()).chain(records) {
+            file.extend((offset as u32).to_be_bytes());
+            file.extend([0; 4]);
+            offset += record.len();
+            data.extend(record);
+        }
+        file.extend(data);
+        file
+    }
+
+    #[test]
+    fn test_read_epub3_toc() {
+        let opf = r#"<package><manifest>
+            <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+            <item id="cover" href="Text/cover.xhtml" media-type="application/xhtml+xml"/>
+            <item id="part1" href="Text/part%201.xhtml" media-type="application/xhtml+xml"/>
+        </manifest><spine>
+            <itemref idref="cover"/>
+            <itemref idref="part1"/>
+        </spine></package>"#;
+        let nav = r#"<html><body>
+            <nav epub:type="landmarks"><ol><li><a href="Text/cover.xhtml">Cover</a></li></ol></nav>
+            <nav epub:type="toc"><ol>
+                <li><a href="Text/part%201.xhtml">Dawn</a><ol>
+                    <li><a href="Text/part%201.xhtml#c1965">1962</a></li>
+                </ol></li>
+            </ol></nav>
+        </body></html>"#;
+        let part = format!(
+            "<html><body><p>{}</p><h2 id=\"c1965\">1962</h2></body></html>",
+            "x".repeat(400)
+        );
+
+        let epub = epub_file(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("OEBPS/content.opf", opf),
+            ("OEBPS/nav.xhtml", nav),
+            ("OEBPS/Text/cover.xhtml", &"c".repeat(300)),
+            ("OEBPS/Text/part 1.xhtml", &part),
+        ]);
+        let toc = read_epub_toc(Cursor::new(epub)).expect("Could not read TOC");
+
+        assert_eq!(
+            toc,
+            vec![
+                TocEntry {
+                    location: 3,
+                    title: "Dawn".to_string()
+                },
+                // Only the text before the heading is counted, not its markup
+                TocEntry {
+                    location: (300 + 400) / BYTES_PER_LOCATION + 1,
+                    title: "1962".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_epub2_toc() {
+        let opf = r#"<package><manifest>
+            <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+            <item id="chapter" href="chapter.html" media-type="application/xhtml+xml"/>
+        </manifest><spine toc="ncx"><itemref idref="chapter"/></spine></package>"#;
+        let ncx = r#"<ncx><navMap>
+            <navPoint id="p1" playOrder="1">
+                <navLabel><text>Part One &amp; Two</text></navLabel>
+                <content src="chapter.html"/>
+                <navPoint id="p2" playOrder="2">
+                    <navLabel><text>Missing</text></navLabel>
+                    <content src="other.html"/>
+                </navPoint>
+            </navPoint>
+        </navMap></ncx>"#;
+
+        let epub = epub_file(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("OEBPS/content.opf", opf),
+            ("OEBPS/toc.ncx", ncx),
+            ("OEBPS/chapter.html", "<p>Text</p>"),
+        ]);
+        let toc = read_epub_toc(Cursor::new(epub)).expect("Could not read TOC");
+
+        // Entries outside of the spine are skipped
+        assert_eq!(
+            toc,
+            vec![TocEntry {
+                location: 1,
+                title: "Part One & Two".to_string()
+            }]
+        );
+
+        let drm = epub_file(&[("META-INF/rights.xml", "<rights/>")]);
+        assert!(read_epub_toc(Cursor::new(drm)).is_err());
+    }
+
+    #[test]
+    fn test_parse_mobi_toc() {
+        let mut text = String::from(
+            "<html><head><guide><reference type=\"toc\" title=\"Contents\" filepos=0000000XXX /></guide></head><body>",
+        );
+        let toc_start = text.len();
+        text = text.replace("0000000XXX", &format!("{toc_start:010}"));
+        text.push_str(
+            "<p><a filepos=0000004500 >Dawn</a></p><p><a filepos=0000009000 ><b>1962 — Part</b></a></p><mbp:pagebreak/>",
+        );
+        text.push_str(&"x".repeat(9000));
+        text.push_str("<a filepos=0000000001 >Not in the table of contents</a></body></html>");
+
+        let toc = parse_mobi_toc(&mobi_file(&text)).expect("Could not read TOC");
+        assert_eq!(
+            toc,
+            vec![
+                TocEntry {
+                    location: 31,
+                    title: "Dawn".to_string()
+                },
+                TocEntry {
+                    location: 61,
+                    title: "1962 — Part".to_string()
+                },
+            ]
+        );
+
+        assert!(parse_mobi_toc(b"not a MOBI file").is_err());
+    }
+
+    #[test]
+    fn test_palmdoc_decompress() {
+        // Literal, space and letter, repeated sequence of 4 bytes 3 bytes back, literal bytes
+        let data = [b'a', b'b', b'c' | 0x80, 0x80, 0x19, 0x02, b'!', b'?'];
+        assert_eq!(palmdoc_decompress(&data), b"ab cb cb!?");
+    }
+
+    #[test]
+    fn test_trailing_size() {
+        // Multibyte flag, and one trailing entry of 3 bytes
+        let record = [b'a', b'b', 0x01, 0xFF, 0xEE, 0x83];
+        assert_eq!(trailing_size(&record, 0b11), 5);
+    }
+
+    #[test]
+    fn test_attribute_chapters() {
+        let dir = create_fixture_directory("toc");
+
+        let mut text = String::from("<guide><reference type=\"toc\" filepos=0000000060 /></guide>");
+        text.push_str(&" ".repeat(60 - text.len()));
+        text.push_str(
+            "<a filepos=0000000000 >Part One</a><a filepos=0000131550 >1965</a><mbp:pagebreak/>",
+        );
+        fs::write(dir.join("Shoe Dog - Phil Knight.mobi"), mobi_file(&text)).unwrap();
+
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let mut books_clips = parse_clips(input.as_str());
+        let attributed = BookFiles::scan(&[&dir])
+            .expect("Could not scan book files")
+            .attribute_chapters(&mut books_clips);
+        fs::remove_dir_all(&dir).ok();
+
+        let shoe_dog = books_clips.last().unwrap();
+        let chapters: Vec<(usize, Option<&str>)> = shoe_dog
+            .clips
+            .iter()
+            .map(|clip| (clip.location.0, clip.chapter.as_deref()))
+            .collect();
+
+        // The second chapter starts at location 878
+        assert_eq!(attributed, shoe_dog.clips.len());
+        assert_eq!(
+            chapters,
+            vec![
+                (877, Some("Part One")),
+                (879, Some("1965")),
+                (901, Some("1965"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attribute_chapters_from_epub() {
+        let dir = create_fixture_directory("toc-epub");
+
+        let opf = r#"<package><manifest>
+            <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+            <item id="part1" href="part1.xhtml" media-type="application/xhtml+xml"/>
+            <item id="part2" href="part2.xhtml" media-type="application/xhtml+xml"/>
+        </manifest><spine><itemref idref="part1"/><itemref idref="part2"/></spine></package>"#;
+        let nav = r#"<nav epub:type="toc"><ol>
+            <li><a href="part1.xhtml">Part One</a></li>
+            <li><a href="part2.xhtml">1965</a></li>
+        </ol></nav>"#;
+        // 877 locations of text, in paragraphs whose markup is not counted
+        let part1 = format!(
+            "<html><head><title>Part One</title></head><body>{}</body></html>",
+            format!("<p class=\"text\">{}</p>", "x".repeat(150)).repeat(877)
+        );
+
+        fs::write(
+            dir.join("Shoe Dog - Phil Knight.epub"),
+            epub_file(&[
+                ("META-INF/container.xml", CONTAINER),
+                ("OEBPS/content.opf", opf),
+                ("OEBPS/nav.xhtml", nav),
+                ("OEBPS/part1.xhtml", &part1),
+                ("OEBPS/part2.xhtml", "<html><body><p>1965</p></body></html>"),
+            ]),
+        )
+        .unwrap();
+
+        let input = fs::read_to_string("tests/data/clippings.txt").expect("Test file not found");
+        let mut books_clips = parse_clips(input.as_str());
+        BookFiles::scan(&[&dir])
+            .expect("Could not scan book files")
+            .attribute_chapters(&mut books_clips);
+        fs::remove_dir_all(&dir).ok();
+
+        let chapters: Vec<(usize, Option<&str>)> = books_clips
+            .last()
+            .unwrap()
+            .clips
+            .iter()
+            .map(|clip| (clip.location.0, clip.chapter.as_deref()))
+            .collect();
+
+        // The second chapter starts at location 878
+        assert_eq!(
+            chapters,
+            vec![
+                (877, Some("Part One")),
+                (879, Some("1965")),
+                (901, Some("1965"))
+            ]
+        );
+    }
+}
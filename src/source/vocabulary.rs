@@ -0,0 +1,118 @@
+use crate::clippings::{author_names, parse_authors};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, TimeZone, Utc};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Word looked up on a Kindle, with the sentence it was read in
+#[derive(Debug, Clone, Serialize)]
+pub struct VocabLookup {
+    /// Identifier of the lookup in the Kindle database, stable between runs
+    pub id: String,
+    /// Word as it was read, like `flâneurs`
+    pub word: String,
+    /// Dictionary form of the word, like `flâneur`
+    pub stem: String,
+    pub language: String,
+    /// Sentence the word was read in
+    pub usage: String,
+    pub book: String,
+    pub author: String,
+    pub date: DateTime<Local>,
+}
+
+/// Kindle Vocabulary Builder `system/vocabulary/vocab.db` database
+///
+/// Lookups are not clips, so they are read on their own rather than through `ClippingSource`
+pub struct KindleVocabulary {
+    path: PathBuf,
+}
+
+impl KindleVocabulary {
+    /// # Arguments
+    ///
+    /// * `path` - The location of the `vocab.db` file
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Reads the lookups, oldest first
+    ///
+    /// # Errors
+    /// Raise if the database cannot be read
+    pub fn read_lookups(&self) -> Result<Vec<VocabLookup>> {
+        let connection = Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Could not open vocabulary database {:?}", self.path))?;
+
+        let mut statement = connection.prepare(
+            "SELECT l.id, w.word, w.stem, w.lang, l.usage, b.title, b.authors, l.timestamp
+             FROM LOOKUPS l
+             JOIN WORDS w ON w.id = l.word_key
+             LEFT JOIN BOOK_INFO b ON b.id = l.book_key
+             ORDER BY l.timestamp",
+        )?;
+
+        let lookups = statement
+            .query_map([], |row| {
+                let text = |idx| -> rusqlite::Result<String> {
+                    Ok(row
+                        .get::<_, Option<String>>(idx)?
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string())
+                };
+
+                Ok(VocabLookup {
+                    id: row.get(0)?,
+                    word: text(1)?,
+                    stem: text(2)?,
+                    language: text(3)?,
+                    usage: text(4)?,
+                    book: text(5)?,
+                    author: author_names(&parse_authors(&text(6)?)),
+                    date: lookup_date(row.get(7)?),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(lookups)
+    }
+}
+
+/// Converts a lookup timestamp, in milliseconds since the Unix epoch, to a local date
+fn lookup_date(timestamp: i64) -> DateTime<Local> {
+    Utc.timestamp_millis_opt(timestamp)
+        .single()
+        .unwrap_or_default()
+        .with_timezone(&Local)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::source::tests::{create_fixture_database, create_fixture_directory, utc_dates};
+    use std::fs;
+
+    /// Reads the lookups of the fixture database, built from its SQL script
+    pub(crate) fn get_test_lookups() -> Vec<VocabLookup> {
+        let dir = create_fixture_directory("vocab");
+        let path = dir.join("vocab.db");
+        create_fixture_database(&path, Path::new("tests/data/vocab.sql"));
+
+        let lookups = KindleVocabulary::new(&path)
+            .read_lookups()
+            .expect("Could not read vocabulary");
+        fs::remove_dir_all(&dir).ok();
+
+        lookups
+    }
+
+    #[test]
+    fn test_read_lookups() {
+        // The lookup of a word missing from the words table is skipped
+        insta::assert_yaml_snapshot!(utc_dates(&get_test_lookups()));
+    }
+}
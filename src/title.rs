@@ -0,0 +1,231 @@
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Words announcing the position of a book in its series, like `Book 1` or `Vol. 2`
+const SERIES_MARKERS: [&str; 5] = ["book", "vol", "volume", "part", "tome"];
+
+/// Book name split in its parts, see `BookTitle::parse`
+///
+/// Kindle names books with their full store title, like
+/// `The Fellowship of the Ring: Being the First Part of The Lord of the Rings (The Lord of the Rings, Book 1)`,
+/// which is too long to be displayed as is.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BookTitle {
+    // Book name as read from the clips
+    pub name: String,
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subtitle: Option<String>,
+    // Series of a trailing marker like `(The Lord of the Rings, Book 1)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_index: Option<f64>,
+    // Other trailing brackets, like `2022`, `NEW` or `Unabridged`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub qualifiers: Vec<String>,
+}
+
+/// How book names are displayed, like Notion page titles and export headings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleStyle {
+    /// The book name as read from the clips
+    Full,
+    /// The title only, like `Building a Second Brain`
+    Title,
+    /// The title and its subtitle, without series markers and qualifiers
+    TitleSubtitle,
+}
+
+impl FromStr for TitleStyle {
+    type Err = Error;
+
+    fn from_str(style: &str) -> Result<Self, Self::Err> {
+        match style {
+            "full" => Ok(Self::Full),
+            "title" => Ok(Self::Title),
+            "title-subtitle" => Ok(Self::TitleSubtitle),
+            _ => bail!("Unknown title style {style:?}, expected full, title or title-subtitle"),
+        }
+    }
+}
+
+impl BookTitle {
+    /// Splits a book name in its title, subtitle, series and qualifiers
+    ///
+    /// Trailing brackets are read from the end: a `Book 1`, `Vol. 2` or `#3` marker gives the
+    /// series, and the others are qualifiers. The subtitle follows the first `:` or em dash.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The book name
+    ///   * Example: `Trillions: How a Band of Wall Street Renegades Invented the Index Fund (2022)`
+    pub fn parse(name: &str) -> Self {
+        let mut book_title = Self {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        let mut rest = name.trim();
+        let mut has_series = false;
+        while let Some((before, group)) = trailing_group(rest) {
+            // A name made of brackets only is the title
+            if before.trim().is_empty() {
+                break;
+            }
+
+            match parse_series(group) {
+                Some((series, index)) if !has_series => {
+                    has_series = true;
+                    book_title.series = series;
+                    book_title.series_index = Some(index);
+                }
+                _ => book_title.qualifiers.insert(0, group.trim().to_string()),
+            }
+            rest = before.trim_end();
+        }
+
+        match split_subtitle(rest) {
+            Some((title, subtitle)) => {
+                book_title.title = title.to_string();
+                book_title.subtitle = Some(subtitle.to_string());
+            }
+            None => book_title.title = rest.to_string(),
+        }
+
+        book_title
+    }
+
+    /// Returns the name of the book to display
+    pub fn format(&self, style: TitleStyle) -> String {
+        match (style, &self.subtitle) {
+            (TitleStyle::Full, _) => self.name.clone(),
+            (TitleStyle::TitleSubtitle, Some(subtitle)) => format!("{}: {subtitle}", self.title),
+            (TitleStyle::Title | TitleStyle::TitleSubtitle, _) => self.title.clone(),
+        }
+    }
+}
+
+/// Returns the text before the trailing bracket of a name and the text inside it, which can
+/// contain nested brackets
+/// * Example: `Dune (Dune Chronicles, Book 1)` gives `Dune ` and `Dune Chronicles, Book 1`
+pub(crate) fn trailing_group(name: &str) -> Option<(&str, &str)> {
+    let close = name.chars().last()?;
+    let open = match close {
+        ')' => '(',
+        ']' => '[',
+        _ => return None,
+    };
+
+    let mut depth = 0;
+    for (idx, c) in name.char_indices().rev() {
+        if c == close {
+            depth += 1;
+        } else if c == open {
+            depth -= 1;
+            if depth == 0 {
+                return Some((&name[..idx], &name[idx + 1..name.len() - 1]));
+            }
+        }
+    }
+
+    None
+}
+
+/// Parses a series marker, returning the series name if any and the position in the series
+/// * Example: `The Lord of the Rings, Book 1`, `The Foundation Series #1` or `Vol. 2`
+fn parse_series(group: &str) -> Option<(Option<String>, f64)> {
+    let words: Vec<&str> = group.split_whitespace().collect();
+    let (last, rest) = words.split_last()?;
+
+    let (index, name) = match last.strip_prefix('#') {
+        Some(index) => (index.parse().ok()?, rest),
+        None => {
+            let (marker, name) = rest.split_last()?;
+            let marker = marker.trim_end_matches('.').to_lowercase();
+            if !SERIES_MARKERS.contains(&marker.as_str()) {
+                return None;
+            }
+            (last.parse().ok()?, name)
+        }
+    };
+
+    let name = name
+        .join(" ")
+        .trim_end_matches([',', ':', '-', ' '])
+        .to_string();
+
+    Some(((!name.is_empty()).then_some(name), index))
+}
+
+/// Splits a title on the first subtitle separator, a `:` followed by a space or an em dash
+fn split_subtitle(name: &str) -> Option<(&str, &str)> {
+    let separator = name.char_indices().find(|&(idx, c)| match c {
+        ':' => name[idx + 1..].starts_with(char::is_whitespace),
+        '—' => true,
+        '–' => name[..idx].ends_with(' ') && name[idx + c.len_utf8()..].starts_with(' '),
+        _ => false,
+    })?;
+
+    let (idx, c) = separator;
+    let (title, subtitle) = (name[..idx].trim(), name[idx + c.len_utf8()..].trim());
+    if title.is_empty() || subtitle.is_empty() {
+        return None;
+    }
+
+    Some((title, subtitle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let titles: Vec<BookTitle> = [
+            "Shoe Dog",
+            "Building a Second Brain: A Proven Method to Organize Your Digital Life and Unlock Your Creative Potential (2022)",
+            "The Fellowship of the Ring: Being the First Part of The Lord of the Rings (The Lord of the Rings, Book 1)",
+            "Foundation (The Foundation Series #1) (NEW)",
+            "Deep Work—Rules for Focused Success in a Distracted World",
+            "Meditations – A New Translation [Unabridged]",
+            "The Expanse (Book 1 (Leviathan Wakes))",
+            "Clean Code (Robert C. Martin Series) ",
+            "Re:Zero",
+            "(2022)",
+        ]
+        .iter()
+        .map(|name| BookTitle::parse(name))
+        .collect();
+
+        insta::assert_yaml_snapshot!(titles);
+    }
+
+    #[test]
+    fn test_format() {
+        let title = BookTitle::parse(
+            "Trillions: How a Band of Wall Street Renegades Invented the Index Fund (2022)",
+        );
+
+        assert_eq!(title.format(TitleStyle::Full), title.name);
+        assert_eq!(title.format(TitleStyle::Title), "Trillions");
+        assert_eq!(
+            title.format(TitleStyle::TitleSubtitle),
+            "Trillions: How a Band of Wall Street Renegades Invented the Index Fund"
+        );
+        assert_eq!(
+            BookTitle::parse("Shoe Dog (NEW)").format(TitleStyle::TitleSubtitle),
+            "Shoe Dog"
+        );
+    }
+
+    #[test]
+    fn test_title_style_from_str() {
+        assert_eq!(
+            "title-subtitle".parse::<TitleStyle>().unwrap(),
+            TitleStyle::TitleSubtitle
+        );
+        assert!("short".parse::<TitleStyle>().is_err());
+    }
+}
@@ -0,0 +1,24 @@
+-- Subset of the tables of AEAnnotation_*.sqlite used by the Apple Books source
+CREATE TABLE ZAEANNOTATION (
+    Z_PK INTEGER PRIMARY KEY,
+    ZANNOTATIONDELETED INTEGER,
+    ZANNOTATIONISUNDERLINE INTEGER,
+    ZANNOTATIONSTYLE INTEGER,
+    ZANNOTATIONTYPE INTEGER,
+    ZPLLOCATIONRANGEEND INTEGER,
+    ZPLLOCATIONRANGESTART INTEGER,
+    ZANNOTATIONCREATIONDATE TIMESTAMP,
+    ZANNOTATIONMODIFICATIONDATE TIMESTAMP,
+    ZANNOTATIONASSETID VARCHAR,
+    ZANNOTATIONLOCATION VARCHAR,
+    ZANNOTATIONNOTE VARCHAR,
+    ZANNOTATIONSELECTEDTEXT VARCHAR,
+    ZFUTUREPROOFING5 VARCHAR
+);
+
+INSERT INTO ZAEANNOTATION VALUES
+    (1, 0, 0, 3, 2, 120, 40, 698000000.5, 698000000.5, 'A1B2C3', 'epubcfi(/6/12[chapter2]!/4/2/6,/1:0,/1:120)', NULL, 'It is never too late to be what you might have been.', 'Chapter 2'),
+    (2, 0, 1, 0, 2, 30, 10, 698100000, 698100000, 'A1B2C3', 'epubcfi(/6/8[chapter1]!/4/2/2,/1:10,/1:30)', 'Underlined, with a note', 'The first sentence.', 'Chapter 1'),
+    (3, 1, 0, 4, 2, 10, 0, 698200000, 698200000, 'A1B2C3', 'epubcfi(/6/14[chapter3]!/4/2,/1:0,/1:10)', NULL, 'A deleted highlight', 'Chapter 3'),
+    (4, 0, 0, 2, 2, 55, 5, 698300000, 698300000, '9F8E7D', 'epubcfi(/6/4[intro]!/4/2,/1:5,/1:55)', NULL, 'Waste no more time arguing what a good man should be. Be one.', NULL),
+    (5, 0, 0, 0, 3, 0, 0, 698400000, 698400000, '9F8E7D', 'epubcfi(/6/4[intro]!/4/2)', NULL, NULL, NULL);
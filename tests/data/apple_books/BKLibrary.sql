@@ -0,0 +1,11 @@
+-- Subset of the tables of BKLibrary*.sqlite used by the Apple Books source
+CREATE TABLE ZBKLIBRARYASSET (
+    Z_PK INTEGER PRIMARY KEY,
+    ZASSETID VARCHAR,
+    ZAUTHOR VARCHAR,
+    ZTITLE VARCHAR
+);
+
+INSERT INTO ZBKLIBRARYASSET VALUES
+    (1, 'A1B2C3', 'George Eliot', 'Middlemarch'),
+    (2, '9F8E7D', 'Marcus Aurelius', 'Meditations');
@@ -0,0 +1,52 @@
+-- Subset of the schema of a Calibre library metadata.db
+CREATE TABLE books (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL DEFAULT 'Unknown',
+    sort TEXT,
+    timestamp TIMESTAMP,
+    pubdate TIMESTAMP,
+    series_index REAL NOT NULL DEFAULT 1.0,
+    author_sort TEXT,
+    isbn TEXT DEFAULT '',
+    lccn TEXT DEFAULT '',
+    path TEXT NOT NULL DEFAULT '',
+    flags INTEGER NOT NULL DEFAULT 1,
+    uuid TEXT,
+    has_cover BOOL DEFAULT 0,
+    last_modified TIMESTAMP
+);
+CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT NOT NULL, sort TEXT, link TEXT NOT NULL DEFAULT '');
+CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, author INTEGER NOT NULL);
+CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT NOT NULL, sort TEXT);
+CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, series INTEGER NOT NULL);
+CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, tag INTEGER NOT NULL);
+CREATE TABLE publishers (id INTEGER PRIMARY KEY, name TEXT NOT NULL, sort TEXT);
+CREATE TABLE books_publishers_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, publisher INTEGER NOT NULL);
+CREATE TABLE languages (id INTEGER PRIMARY KEY, lang_code TEXT NOT NULL);
+CREATE TABLE books_languages_link (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, lang_code INTEGER NOT NULL, item_order INTEGER NOT NULL DEFAULT 0);
+CREATE TABLE identifiers (id INTEGER PRIMARY KEY, book INTEGER NOT NULL, type TEXT NOT NULL DEFAULT 'isbn', val TEXT NOT NULL);
+
+-- Shoe Dog has its publisher in its OPF file only
+INSERT INTO books (id, title, pubdate, series_index, path, has_cover) VALUES
+    (1, 'Shoe Dog: A Memoir by the Creator of Nike', '2016-04-26 04:00:00+00:00', 1.0, 'Phil Knight/Shoe Dog_ A Memoir by the Creator of Nike (1)', 1),
+    (2, 'How to Win Friends & Influence People', '0101-01-01 00:00:00+00:00', 1.0, 'Dale Carnegie/How to Win Friends & Influence People (2)', 0),
+    (3, 'Building a Second Brain', '2022-06-14 00:00:00+00:00', 1.0, 'Tiago Forte/Building a Second Brain (3)', 0),
+    (4, 'Trillions', '2019-01-01 00:00:00+00:00', 2.0, 'Jane Doe/Trillions (4)', 0);
+
+INSERT INTO authors (id, name) VALUES (1, 'Phil Knight'), (2, 'Dale Carnegie'), (3, 'Tiago Forte'), (4, 'Jane Doe');
+INSERT INTO books_authors_link (book, author) VALUES (1, 1), (2, 2), (3, 3), (4, 4);
+
+INSERT INTO series (id, name) VALUES (1, 'Second Brain'), (2, 'Numbers');
+INSERT INTO books_series_link (book, series) VALUES (3, 1), (4, 2);
+
+INSERT INTO tags (id, name) VALUES (1, 'Business'), (2, 'Biography'), (3, 'Productivity'), (4, 'Self-help');
+INSERT INTO books_tags_link (book, tag) VALUES (1, 2), (1, 1), (2, 4), (3, 3), (3, 4);
+
+INSERT INTO publishers (id, name) VALUES (1, 'Vermilion'), (2, 'Atria Books');
+INSERT INTO books_publishers_link (book, publisher) VALUES (2, 1), (3, 2);
+
+INSERT INTO languages (id, lang_code) VALUES (1, 'eng');
+INSERT INTO books_languages_link (book, lang_code) VALUES (1, 1), (2, 1), (3, 1);
+
+INSERT INTO identifiers (book, type, val) VALUES (1, 'isbn', '9781501135910'), (1, 'amazon', 'B00ZYVIVA0'), (3, 'isbn', '9781982167387');
@@ -0,0 +1,42 @@
+-- Subset of the tables of .kobo/KoboReader.sqlite used by the Kobo source
+CREATE TABLE content (
+    ContentID TEXT NOT NULL,
+    ContentType TEXT NOT NULL,
+    BookID TEXT,
+    Title TEXT,
+    Attribution TEXT,
+    VolumeIndex INTEGER,
+    ___PercentRead INTEGER,
+    PRIMARY KEY (ContentID)
+);
+
+CREATE TABLE Bookmark (
+    BookmarkID TEXT NOT NULL,
+    VolumeID TEXT NOT NULL,
+    ContentID TEXT NOT NULL,
+    Text TEXT,
+    Annotation TEXT,
+    ChapterProgress REAL NOT NULL DEFAULT 0,
+    Hidden BOOL NOT NULL DEFAULT 'false',
+    DateCreated TEXT,
+    Type TEXT,
+    PRIMARY KEY (BookmarkID)
+);
+
+INSERT INTO content VALUES
+    ('file:///mnt/onboard/Shoe Dog.kepub.epub', '6', NULL, 'Shoe Dog', 'Phil Knight', -1, 42),
+    ('file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!dawn.xhtml-1', '9', 'file:///mnt/onboard/Shoe Dog.kepub.epub', 'Dawn', NULL, 0, 0),
+    ('file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!1962.xhtml-1', '9', 'file:///mnt/onboard/Shoe Dog.kepub.epub', '1962', NULL, 1, 0),
+    ('file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!1963.xhtml-1', '9', 'file:///mnt/onboard/Shoe Dog.kepub.epub', '1963', NULL, 2, 0),
+    ('file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!1964.xhtml-1', '9', 'file:///mnt/onboard/Shoe Dog.kepub.epub', '1964', NULL, 3, 0),
+    ('file:///mnt/onboard/Meditations.epub', '6', NULL, 'Meditations', 'Marcus Aurelius', -1, 10);
+
+INSERT INTO Bookmark VALUES
+    ('b1', 'file:///mnt/onboard/Shoe Dog.kepub.epub', 'file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!dawn.xhtml', '  I was up before the others.
+', NULL, 0.1, 'false', '2023-02-13T00:29:40.000', 'highlight'),
+    ('b2', 'file:///mnt/onboard/Shoe Dog.kepub.epub', 'file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!1962.xhtml', 'Let everyone else call your idea crazy.', 'Keep going', 0.5, 'false', '2023-02-14T21:02:11Z', 'note'),
+    ('b3', 'file:///mnt/onboard/Shoe Dog.kepub.epub', 'file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!1963.xhtml', NULL, NULL, 0.25, 'false', '2023-02-15T08:00:00.000', 'dogear'),
+    ('b4', 'file:///mnt/onboard/Shoe Dog.kepub.epub', 'file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!1964.xhtml', 'A deleted highlight', NULL, 0.5, 'true', '2023-02-15T09:00:00.000', 'highlight'),
+    ('b5', 'file:///mnt/onboard/Meditations.epub', 'file:///mnt/onboard/Meditations.epub#(3)text/part0003.html', 'The impediment to action advances action.', NULL, 0.75, 'false', '2023-03-01T12:00:00.000', 'highlight'),
+    ('b6', 'file:///mnt/onboard/Shoe Dog.kepub.epub', 'file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!dawn.xhtml', 'Tomorrow I will run again.', NULL, 0.1, 'false', '2023-02-16T07:00:00.000', 'highlight'),
+    ('b7', 'file:///mnt/onboard/Shoe Dog.kepub.epub', 'file:///mnt/onboard/Shoe Dog.kepub.epub!OEBPS!dawn.xhtml', 'A highlight without date', NULL, 0.2, 'false', NULL, 'highlight');
@@ -0,0 +1,53 @@
+-- we can read Lua syntax here!
+return {
+    ["annotations"] = {
+        [1] = {
+            ["chapter"] = "Dawn",
+            ["color"] = "yellow",
+            ["datetime"] = "2023-02-13 00:29:40",
+            ["drawer"] = "lighten",
+            ["page"] = "/body/DocFragment[5]/body/p[3]/text().0",
+            ["pageno"] = 3,
+            ["pos0"] = "/body/DocFragment[5]/body/p[3]/text().0",
+            ["pos1"] = "/body/DocFragment[5]/body/p[3]/text().28",
+            ["text"] = "I was up before the others.",
+        },
+        [2] = {
+            ["chapter"] = "1962",
+            ["datetime"] = "2023-02-14 21:02:11",
+            ["note"] = "Keep going,\
+whatever they say",
+            ["page"] = "/body/DocFragment[7]/body/p[12]/text().4",
+            ["pageno"] = 12,
+            ["pos0"] = "/body/DocFragment[7]/body/p[12]/text().4",
+            ["pos1"] = "/body/DocFragment[7]/body/p[12]/text().60",
+            ["text"] = "Let everyone else call your idea \"crazy\" \226\128\148 just keep going.",
+        },
+        [3] = {
+            ["chapter"] = "1962",
+            ["datetime"] = "2023-02-14 20:55:02",
+            ["page"] = "/body/DocFragment[7]/body/p[2]/text().0",
+            ["pageno"] = 12,
+            ["pos0"] = "/body/DocFragment[7]/body/p[2]/text().0",
+            ["pos1"] = "/body/DocFragment[7]/body/p[2]/text().20",
+            ["text"] = "Don't stop.",
+        },
+        [4] = {
+            ["chapter"] = "1963",
+            ["datetime"] = "2023-02-15 08:00:00",
+            ["page"] = "/body/DocFragment[8]/body/p[1]/text().0",
+            ["pageno"] = 20,
+            ["text"] = "in 1963",
+        },
+    },
+    ["doc_pages"] = 40,
+    ["doc_props"] = {
+        ["authors"] = "Phil Knight",
+        ["language"] = "en",
+        ["title"] = "Shoe Dog",
+    },
+    ["percent_finished"] = 0.5,
+    ["summary"] = {
+        ["status"] = "reading",
+    },
+}
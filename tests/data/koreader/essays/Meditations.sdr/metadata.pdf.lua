@@ -0,0 +1,20 @@
+-- we can read Lua syntax here!
+return {
+    ["annotations"] = {
+        [1] = {
+            ["datetime"] = "2023-03-01 12:00:00",
+            ["page"] = 7,
+            ["pos0"] = {
+                ["page"] = 7,
+                ["x"] = 120.5,
+                ["y"] = 300,
+            },
+            ["text"] = "The impediment to action advances action.",
+        },
+    },
+    ["doc_pages"] = 70,
+    ["doc_props"] = {
+        ["authors"] = "",
+        ["title"] = "",
+    },
+}
@@ -0,0 +1,63 @@
+-- we can read Lua syntax here!
+return {
+    ["bookmarks"] = {
+        [1] = {
+            ["chapter"] = "Prologue",
+            ["datetime"] = "2023-04-02 10:15:00",
+            ["highlighted"] = true,
+            ["notes"] = "Index funds were a heresy.",
+            ["page"] = "/body/DocFragment[3]/body/p[8]/text().0",
+            ["pos0"] = "/body/DocFragment[3]/body/p[8]/text().0",
+            ["pos1"] = "/body/DocFragment[3]/body/p[8]/text().26",
+            ["text"] = "Still true in some places",
+        },
+        [2] = {
+            ["chapter"] = "Prologue",
+            ["datetime"] = "2023-04-02 10:05:00",
+            ["highlighted"] = true,
+            ["notes"] = "It began with a bet.",
+            ["page"] = "/body/DocFragment[3]/body/p[2]/text().0",
+            ["pos0"] = "/body/DocFragment[3]/body/p[2]/text().0",
+            ["pos1"] = "/body/DocFragment[3]/body/p[2]/text().20",
+            ["text"] = "Page 5 It began with a bet. @ 2023-04-02 10:05:00",
+        },
+        [3] = {
+            ["chapter"] = "Chapter 1",
+            ["datetime"] = "2023-04-03 21:00:00",
+            ["notes"] = "Page 9",
+            ["page"] = 9,
+        },
+    },
+    ["doc_pages"] = 300,
+    ["doc_props"] = {
+        ["authors"] = "Robin Wigglesworth",
+        ["title"] = "Trillions",
+    },
+    ["highlight"] = {
+        [5] = {
+            [1] = {
+                ["chapter"] = "Prologue",
+                ["datetime"] = "2023-04-02 10:05:00",
+                ["drawer"] = "lighten",
+                ["pos0"] = "/body/DocFragment[3]/body/p[2]/text().0",
+                ["pos1"] = "/body/DocFragment[3]/body/p[2]/text().20",
+                ["text"] = "It began with a bet.",
+            },
+            [2] = {
+                ["chapter"] = "Prologue",
+                ["datetime"] = "2023-04-02 10:15:00",
+                ["drawer"] = "lighten",
+                ["pos0"] = "/body/DocFragment[3]/body/p[8]/text().0",
+                ["pos1"] = "/body/DocFragment[3]/body/p[8]/text().26",
+                ["text"] = "Index funds were a heresy.",
+            },
+            [3] = {
+                ["chapter"] = "Prologue",
+                ["drawer"] = "lighten",
+                ["pos0"] = "/body/DocFragment[3]/body/p[9]/text().0",
+                ["pos1"] = "/body/DocFragment[3]/body/p[9]/text().12",
+                ["text"] = "A highlight without datetime.",
+            },
+        },
+    },
+}
@@ -0,0 +1,42 @@
+-- Subset of the tables of system/vocabulary/vocab.db used by the vocabulary source
+CREATE TABLE WORDS (
+    id TEXT PRIMARY KEY NOT NULL UNIQUE,
+    word TEXT,
+    stem TEXT,
+    lang TEXT,
+    category INTEGER DEFAULT 0,
+    timestamp INTEGER DEFAULT 0,
+    profileid TEXT
+);
+
+CREATE TABLE BOOK_INFO (
+    id TEXT PRIMARY KEY NOT NULL UNIQUE,
+    asin TEXT,
+    guid TEXT,
+    lang TEXT,
+    title TEXT,
+    authors TEXT
+);
+
+CREATE TABLE LOOKUPS (
+    id TEXT PRIMARY KEY NOT NULL,
+    word_key TEXT,
+    book_key TEXT,
+    dict_key TEXT,
+    pos TEXT,
+    usage TEXT,
+    timestamp INTEGER DEFAULT 0
+);
+
+INSERT INTO WORDS VALUES
+    ('en:reflexively', 'reflexively', 'reflexive', 'en', 0, 1676215780000, ''),
+    ('fr:flâneur', 'flâneurs', 'flâneur', 'fr', 100, 1676302180000, '');
+
+INSERT INTO BOOK_INFO VALUES
+    ('B00ZYVIVA0:ABCD1234', 'B00ZYVIVA0', 'ABCD1234', 'en', 'Shoe Dog', 'Phil Knight'),
+    ('B0FRENCH01:EFGH5678', 'B0FRENCH01', 'EFGH5678', 'fr', 'Le Spleen de Paris', 'Charles Baudelaire');
+
+INSERT INTO LOOKUPS VALUES
+    ('B00ZYVIVA0:ABCD1234:877:reflexively', 'en:reflexively', 'B00ZYVIVA0:ABCD1234', 'Oxford', '877', 'People reflexively assume that competition is always a good thing', 1676215780000),
+    ('B0FRENCH01:EFGH5678:120:flâneurs', 'fr:flâneur', 'B0FRENCH01:EFGH5678', 'Larousse', '120', 'Les flâneurs de la ville', 1676302180000),
+    ('B0MISSING0:0000:10:orphan', 'en:orphan', 'B0MISSING0:0000', 'Oxford', '10', 'A lookup of a deleted word', 1676302190000);